@@ -0,0 +1,27 @@
+use carbond_client::forecast;
+use chrono::{Duration, Utc};
+use uom::si::mass_per_energy::gram_per_kilowatt_hour;
+
+use color_eyre::eyre::Result;
+
+/// This example shows how the forecast module can be used to schedule a batch job.
+/// It searches the published forecast for the start time with the lowest average
+/// carbon intensity for a job of two hours that has to finish within the next day.
+#[tokio::main]
+async fn main() -> Result<()> {
+    color_eyre::install()?;
+
+    // the job takes two hours and must be done within 24 hours
+    let duration = Duration::hours(2);
+    let earliest_start = Utc::now();
+    let deadline = earliest_start + Duration::hours(24);
+
+    // search the forecast for the window with the lowest average carbon intensity
+    let window = forecast::best_window(duration, earliest_start, deadline).await?;
+    println!(
+        "Starting the job at {} results in an expected carbon intensity of {:?} gCO2/kWh.",
+        window.start,
+        window.average_intensity.get::<gram_per_kilowatt_hour>()
+    );
+    Ok(())
+}
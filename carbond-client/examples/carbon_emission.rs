@@ -29,8 +29,16 @@ async fn main() -> Result<()> {
     // calculate energy "usage" by subtracting the accumulated energy before the call from the accumulated energy after the call
     let energy: Energy = accumulated_energy_after - accumulated_energy_before;
 
-    // load current carbon intensity
+    // load current carbon intensity and warn if it is outdated
     let carbon_intensity: CarbonIntensity = power_supply::current_carbon_intensity().await?;
+    let metadata = power_supply::current_carbon_intensity_metadata().await?;
+    if metadata.is_stale() {
+        println!(
+            "Carbon intensity from {} is stale, it was observed {} minutes ago.",
+            metadata.provider,
+            metadata.age().num_minutes()
+        );
+    }
 
     // calculate the emission by multiplying the carbon intensity with the accumulated energy difference
     let carbon_emission: Mass = carbon_intensity * energy;
@@ -1,4 +1,5 @@
 use carbond_lib::metrics::cpu_cycles::CpuCycleIntensity;
+use carbond_lib::metrics::metadata::MetricMetadata;
 use carbond_lib::metrics::metric::{Metric, MetricError};
 use uom::si::f64::Mass;
 
@@ -6,3 +7,8 @@ use uom::si::f64::Mass;
 pub async fn load_cpu_embodied_intensity() -> Result<Mass, MetricError> {
     Ok(CpuCycleIntensity::try_read_from_fs().await?.get_value())
 }
+
+/// Loads the metadata of the embodied intensity of the CPU from the file system.
+pub async fn load_cpu_embodied_intensity_metadata() -> Result<MetricMetadata, MetricError> {
+    CpuCycleIntensity::try_read_metadata_from_fs().await
+}
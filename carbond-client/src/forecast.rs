@@ -0,0 +1,203 @@
+use carbond_lib::metrics::forecast::CarbonIntensityForecast;
+use carbond_lib::metrics::metadata::MetricMetadata;
+use carbond_lib::metrics::metric::{Metric, MetricError};
+use chrono::{DateTime, Duration, Utc};
+use thiserror::Error;
+use uom::si::f64::MassPerEnergy;
+
+pub use carbond_lib::metrics::forecast::ForecastPoint;
+
+use crate::intensity::average_intensity;
+
+/// Loads the carbon intensity forecast from the file system.
+/// The points are ordered by their start time.
+pub async fn carbon_intensity_forecast() -> Result<Vec<ForecastPoint>, MetricError> {
+    Ok(CarbonIntensityForecast::try_read_from_fs()
+        .await?
+        .get_value())
+}
+
+/// Loads the metadata of the carbon intensity forecast from the file system.
+pub async fn carbon_intensity_forecast_metadata() -> Result<MetricMetadata, MetricError> {
+    CarbonIntensityForecast::try_read_metadata_from_fs().await
+}
+
+/// Time frame to run a job in and the carbon intensity expected during it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExecutionWindow {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    /// Average carbon intensity over the window.
+    pub average_intensity: MassPerEnergy,
+}
+
+#[derive(Error, Debug)]
+pub enum WindowError {
+    #[error("Could not load the forecast.")]
+    Metric(#[from] MetricError),
+    #[error("The forecast does not cover a window of the requested duration before the deadline.")]
+    NoWindow,
+}
+
+/// Finds the start time that minimises the average carbon intensity of a job
+/// with the given duration, based on the published forecast.
+/// The job starts at `earliest_start` or later and ends before `deadline`.
+pub async fn best_window(
+    duration: Duration,
+    earliest_start: DateTime<Utc>,
+    deadline: DateTime<Utc>,
+) -> Result<ExecutionWindow, WindowError> {
+    let points = carbon_intensity_forecast().await?;
+    find_best_window(&points, duration, earliest_start, deadline).ok_or(WindowError::NoWindow)
+}
+
+/// Finds the window with the lowest average carbon intensity in the given forecast.
+/// Windows must be fully covered by the forecast, partially covered forecast
+/// intervals are weighted by their overlap with the window.
+/// Returns the earliest of equally good windows.
+pub fn find_best_window(
+    points: &[ForecastPoint],
+    duration: Duration,
+    earliest_start: DateTime<Utc>,
+    deadline: DateTime<Utc>,
+) -> Option<ExecutionWindow> {
+    if duration <= Duration::zero() {
+        return None;
+    }
+    let forecast_start = points.iter().map(|point| point.start).min()?;
+    let forecast_end = points.iter().map(|point| point.end).max()?;
+    let first_start = earliest_start.max(forecast_start);
+    let last_start = deadline.min(forecast_end) - duration;
+    if first_start > last_start {
+        return None;
+    }
+
+    // The average is piecewise linear in the start time, so its minimum lies where
+    // the window's start or end meets an interval boundary or the allowed range ends.
+    let mut candidates: Vec<DateTime<Utc>> = points
+        .iter()
+        .flat_map(|point| [point.start, point.end])
+        .flat_map(|boundary| [boundary, boundary - duration])
+        .chain([first_start, last_start])
+        .filter(|start| (first_start..=last_start).contains(start))
+        .collect();
+    candidates.sort();
+    candidates.dedup();
+
+    let mut best: Option<ExecutionWindow> = None;
+    for start in candidates {
+        let Some(average_intensity) = average_intensity(
+            points.iter().map(|p| (p.start, p.end, p.intensity)),
+            start,
+            start + duration,
+        ) else {
+            continue;
+        };
+        if best
+            .as_ref()
+            .map(|best| average_intensity < best.average_intensity)
+            .unwrap_or(true)
+        {
+            best = Some(ExecutionWindow {
+                start,
+                end: start + duration,
+                average_intensity,
+            });
+        }
+    }
+    best
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use uom::si::mass_per_energy::gram_per_kilowatt_hour;
+
+    fn time(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 5, 1, hour, minute, 0).unwrap()
+    }
+
+    fn hourly(intensities: &[f64]) -> Vec<ForecastPoint> {
+        intensities
+            .iter()
+            .enumerate()
+            .map(|(i, intensity)| ForecastPoint {
+                start: time(i as u32, 0),
+                end: time(i as u32 + 1, 0),
+                intensity: MassPerEnergy::new::<gram_per_kilowatt_hour>(*intensity),
+            })
+            .collect()
+    }
+
+    fn grams(intensity: MassPerEnergy) -> f64 {
+        intensity.get::<gram_per_kilowatt_hour>()
+    }
+
+    #[test]
+    fn test_best_window_aligned() {
+        let points = hourly(&[300.0, 200.0, 100.0, 150.0, 400.0]);
+
+        let window = find_best_window(&points, Duration::hours(2), time(0, 0), time(5, 0)).unwrap();
+
+        assert_eq!(window.start, time(2, 0));
+        assert_eq!(window.end, time(4, 0));
+        assert!((grams(window.average_intensity) - 125.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_best_window_partially_covered_intervals() {
+        let points = hourly(&[300.0, 100.0, 300.0]);
+
+        let window =
+            find_best_window(&points, Duration::minutes(90), time(0, 0), time(3, 0)).unwrap();
+
+        // any window containing the whole low hour is optimal, the earliest one is chosen
+        assert_eq!(window.start, time(0, 30));
+        assert!((grams(window.average_intensity) - (300.0 * 0.5 + 100.0) / 1.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_best_window_respects_bounds() {
+        let points = hourly(&[100.0, 300.0, 200.0, 50.0]);
+
+        let window =
+            find_best_window(&points, Duration::minutes(30), time(0, 45), time(3, 15)).unwrap();
+
+        assert_eq!(window.start, time(2, 45));
+        assert_eq!(window.end, time(3, 15));
+
+        let window =
+            find_best_window(&points, Duration::minutes(30), time(0, 45), time(3, 0)).unwrap();
+
+        assert_eq!(window.start, time(0, 45));
+    }
+
+    #[test]
+    fn test_best_window_skips_gaps() {
+        let mut points = hourly(&[100.0, 500.0, 50.0, 400.0]);
+        points.remove(1);
+
+        let window = find_best_window(&points, Duration::hours(2), time(0, 0), time(4, 0)).unwrap();
+
+        assert_eq!(window.start, time(2, 0));
+    }
+
+    #[test]
+    fn test_best_window_none() {
+        let points = hourly(&[100.0, 200.0]);
+
+        assert_eq!(
+            find_best_window(&points, Duration::hours(3), time(0, 0), time(5, 0)),
+            None
+        );
+        assert_eq!(
+            find_best_window(&points, Duration::hours(1), time(3, 0), time(5, 0)),
+            None
+        );
+        assert_eq!(
+            find_best_window(&[], Duration::hours(1), time(0, 0), time(5, 0)),
+            None
+        );
+    }
+}
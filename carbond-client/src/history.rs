@@ -0,0 +1,202 @@
+use carbond_lib::history::{HistoryError, HistoryStore};
+use chrono::{DateTime, Duration, Utc};
+use thiserror::Error;
+use uom::si::{
+    energy::kilowatt_hour,
+    f64::{Energy, Mass, MassPerEnergy},
+    mass::gram,
+    mass_per_energy::gram_per_kilowatt_hour,
+};
+
+pub use carbond_lib::history::IntensityRecord;
+
+use crate::intensity::{average_intensity, seconds};
+
+/// Time around a looked up timestamp that is searched for neighbouring records.
+/// Covers daily aggregates on both sides.
+const LOOKUP_MARGIN_DAYS: i64 = 2;
+
+#[derive(Error, Debug)]
+pub enum LookupError {
+    #[error("Could not load the intensity history.")]
+    History(#[from] HistoryError),
+    #[error("The intensity history does not cover {0}.")]
+    NotCovered(DateTime<Utc>),
+    #[error("Energy samples must be ordered by time and increasing.")]
+    InvalidSamples,
+}
+
+/// Reading of an accumulating energy counter, e.g. of intel-rapl, at a point in time.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnergySample {
+    pub timestamp: DateTime<Utc>,
+    /// Energy consumed since an arbitrary but fixed reference point.
+    pub energy: Energy,
+}
+
+/// Loads the carbon intensities that were valid between `start` and `end` from the
+/// history kept by carbond.
+/// Older time ranges are answered with hourly or daily aggregates.
+pub async fn carbon_intensity_history(
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<Vec<IntensityRecord>, HistoryError> {
+    HistoryStore::default().query(start, end).await
+}
+
+/// Loads the carbon intensity at the given time from the history.
+/// Interpolates linearly between the start of consecutive records.
+pub async fn carbon_intensity_at(timestamp: DateTime<Utc>) -> Result<MassPerEnergy, LookupError> {
+    let margin = Duration::days(LOOKUP_MARGIN_DAYS);
+    let records = carbon_intensity_history(timestamp - margin, timestamp + margin).await?;
+    intensity_at(&records, timestamp).ok_or(LookupError::NotCovered(timestamp))
+}
+
+/// Calculates the carbon emission of the energy consumed between consecutive samples.
+/// The energy of each interval is distributed evenly over its duration and paired with
+/// the carbon intensities that were valid during the interval.
+pub async fn integrate_emission(samples: &[EnergySample]) -> Result<Mass, LookupError> {
+    let (Some(first), Some(last)) = (samples.first(), samples.last()) else {
+        return Ok(Mass::new::<gram>(0.0));
+    };
+    let records = carbon_intensity_history(first.timestamp, last.timestamp).await?;
+    emission(&records, samples)
+}
+
+/// Calculates the carbon intensity at the given time from ordered records.
+fn intensity_at(records: &[IntensityRecord], time: DateTime<Utc>) -> Option<MassPerEnergy> {
+    let i = records
+        .iter()
+        .rposition(|record| record.start <= time && time < record.end)?;
+    let record = &records[i];
+    let value = record.intensity.get::<gram_per_kilowatt_hour>();
+    let value = match records.get(i + 1) {
+        // interpolate only towards contiguous records
+        Some(next) if next.start <= record.end && next.start > record.start => {
+            let next_value = next.intensity.get::<gram_per_kilowatt_hour>();
+            let progress = seconds(time - record.start) / seconds(next.start - record.start);
+            value + (next_value - value) * progress
+        }
+        _ => value,
+    };
+    Some(MassPerEnergy::new::<gram_per_kilowatt_hour>(value))
+}
+
+/// Calculates the carbon emission of the samples with the given ordered records.
+fn emission(records: &[IntensityRecord], samples: &[EnergySample]) -> Result<Mass, LookupError> {
+    let mut grams = 0.0;
+    for pair in samples.windows(2) {
+        let (from, to) = (&pair[0], &pair[1]);
+        let energy = to.energy - from.energy;
+        if to.timestamp < from.timestamp || energy.get::<kilowatt_hour>() < 0.0 {
+            return Err(LookupError::InvalidSamples);
+        }
+        if energy.get::<kilowatt_hour>() == 0.0 {
+            continue;
+        }
+        let intensity = if to.timestamp == from.timestamp {
+            intensity_at(records, from.timestamp)
+        } else {
+            average_intensity(
+                records.iter().map(|r| (r.start, r.end, r.intensity)),
+                from.timestamp,
+                to.timestamp,
+            )
+        }
+        .ok_or(LookupError::NotCovered(from.timestamp))?;
+        grams += (intensity * energy).get::<gram>();
+    }
+    Ok(Mass::new::<gram>(grams))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn time(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2023, 5, 1, hour, minute, 0).unwrap()
+    }
+
+    fn record(hour: u32, hours: u32, intensity: f64) -> IntensityRecord {
+        IntensityRecord {
+            start: time(hour, 0),
+            end: time(hour + hours, 0),
+            intensity: MassPerEnergy::new::<gram_per_kilowatt_hour>(intensity),
+        }
+    }
+
+    fn sample(hour: u32, minute: u32, kwh: f64) -> EnergySample {
+        EnergySample {
+            timestamp: time(hour, minute),
+            energy: Energy::new::<kilowatt_hour>(kwh),
+        }
+    }
+
+    fn grams_per_kwh(intensity: MassPerEnergy) -> f64 {
+        intensity.get::<gram_per_kilowatt_hour>()
+    }
+
+    #[test]
+    fn test_intensity_at_interpolates() {
+        let records = vec![record(12, 1, 100.0), record(13, 1, 200.0)];
+
+        let intensity = intensity_at(&records, time(12, 15)).unwrap();
+
+        assert!((grams_per_kwh(intensity) - 125.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_intensity_at_last_record_and_gaps() {
+        let records = vec![record(12, 1, 100.0), record(14, 1, 200.0)];
+
+        assert!(
+            (grams_per_kwh(intensity_at(&records, time(12, 30)).unwrap()) - 100.0).abs() < 1e-9
+        );
+        assert!(
+            (grams_per_kwh(intensity_at(&records, time(14, 30)).unwrap()) - 200.0).abs() < 1e-9
+        );
+        assert_eq!(intensity_at(&records, time(13, 30)), None);
+        assert_eq!(intensity_at(&records, time(15, 0)), None);
+    }
+
+    #[test]
+    fn test_emission_spans_multiple_records() {
+        let records = vec![record(12, 1, 100.0), record(13, 1, 300.0)];
+        // 2 kWh evenly consumed from 12:30 to 13:30
+        let samples = vec![sample(12, 30, 1.0), sample(13, 30, 3.0)];
+
+        let emission = emission(&records, &samples).unwrap();
+
+        assert!((emission.get::<gram>() - 400.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_emission_per_interval() {
+        let records = vec![record(12, 1, 100.0), record(13, 1, 300.0)];
+        let samples = vec![
+            sample(12, 0, 0.0),
+            sample(12, 30, 1.0),
+            sample(13, 0, 1.0),
+            sample(14, 0, 2.0),
+        ];
+
+        let emission = emission(&records, &samples).unwrap();
+
+        assert!((emission.get::<gram>() - 400.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_emission_errors() {
+        let records = vec![record(12, 1, 100.0)];
+
+        assert!(matches!(
+            emission(&records, &[sample(12, 0, 0.0), sample(13, 30, 1.0)]),
+            Err(LookupError::NotCovered(_))
+        ));
+        assert!(matches!(
+            emission(&records, &[sample(12, 0, 1.0), sample(12, 30, 0.0)]),
+            Err(LookupError::InvalidSamples)
+        ));
+    }
+}
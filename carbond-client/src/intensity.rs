@@ -0,0 +1,94 @@
+use chrono::{DateTime, Duration, Utc};
+use uom::si::{f64::MassPerEnergy, mass_per_energy::gram_per_kilowatt_hour};
+
+/// Calculates the time weighted average carbon intensity between `start` and `end`
+/// from intervals given as their start, end and carbon intensity.
+/// Overlapping intervals are cut at the start of the next interval, so a later interval
+/// replaces an earlier one, e.g. after falling back to another provider.
+/// Returns `None` if the intervals do not cover the whole time frame.
+pub(crate) fn average_intensity(
+    intervals: impl IntoIterator<Item = (DateTime<Utc>, DateTime<Utc>, MassPerEnergy)>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Option<MassPerEnergy> {
+    let mut intervals: Vec<_> = intervals.into_iter().collect();
+    intervals.sort_by_key(|(interval_start, _, _)| *interval_start);
+    let next_starts = intervals
+        .iter()
+        .skip(1)
+        .map(|(next_start, _, _)| Some(*next_start))
+        .chain([None]);
+
+    let mut covered = Duration::zero();
+    let mut weighted_sum = 0.0;
+    for (&(interval_start, interval_end, intensity), next_start) in
+        intervals.iter().zip(next_starts)
+    {
+        let interval_end = next_start.map_or(interval_end, |next| interval_end.min(next));
+        let overlap = interval_end.min(end) - interval_start.max(start);
+        if overlap > Duration::zero() {
+            covered += overlap;
+            weighted_sum += intensity.get::<gram_per_kilowatt_hour>() * seconds(overlap);
+        }
+    }
+    if covered < end - start {
+        return None;
+    }
+    Some(MassPerEnergy::new::<gram_per_kilowatt_hour>(
+        weighted_sum / seconds(end - start),
+    ))
+}
+
+pub(crate) fn seconds(duration: Duration) -> f64 {
+    duration.num_milliseconds() as f64 / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn interval(hour: u32, intensity: f64) -> (DateTime<Utc>, DateTime<Utc>, MassPerEnergy) {
+        let start = Utc.with_ymd_and_hms(2023, 5, 1, hour, 0, 0).unwrap();
+        (
+            start,
+            start + Duration::hours(1),
+            MassPerEnergy::new::<gram_per_kilowatt_hour>(intensity),
+        )
+    }
+
+    #[test]
+    fn test_average_intensity() {
+        let intervals = [interval(12, 100.0), interval(13, 300.0)];
+        let start = intervals[0].0 + Duration::minutes(30);
+
+        let average = average_intensity(intervals, start, start + Duration::hours(1)).unwrap();
+
+        assert!((average.get::<gram_per_kilowatt_hour>() - 200.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_average_intensity_not_covered() {
+        let intervals = [interval(12, 100.0), interval(14, 300.0)];
+        let start = intervals[0].0;
+
+        assert!(average_intensity(intervals, start, start + Duration::hours(3)).is_none());
+    }
+
+    #[test]
+    fn test_average_intensity_overlapping() {
+        let (start, end, _) = interval(12, 100.0);
+        // a fallback provider reported at 12:10 while the first record was still valid
+        let fallback = (
+            start + Duration::minutes(10),
+            end + Duration::minutes(10),
+            MassPerEnergy::new::<gram_per_kilowatt_hour>(400.0),
+        );
+        let intervals = [fallback, interval(12, 100.0)];
+
+        let average = average_intensity(intervals, start, end).unwrap();
+
+        assert!((average.get::<gram_per_kilowatt_hour>() - 350.0).abs() < 1e-9);
+        assert!(average_intensity(intervals, start - Duration::minutes(5), end).is_none());
+    }
+}
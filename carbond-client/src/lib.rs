@@ -1,2 +1,5 @@
 pub mod embodied;
+pub mod forecast;
+pub mod history;
+mod intensity;
 pub mod power_supply;
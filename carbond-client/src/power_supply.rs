@@ -1,14 +1,52 @@
-use carbond_lib::metrics::carbon_intensity::CarbonIntensity;
+use carbond_lib::metrics::carbon_intensity::{
+    AverageCarbonIntensity, CarbonIntensity, MarginalCarbonIntensity,
+};
+use carbond_lib::metrics::metadata::MetricMetadata;
 use carbond_lib::metrics::metric::{Metric, MetricError};
 use uom::si::f64::{Energy, Mass, MassPerEnergy};
 
 /// Loads the current carbon intensity from the file system.
+/// It is either marginal or average depending on the highest priority provider,
+/// see [`current_marginal_carbon_intensity`] and [`current_average_carbon_intensity`].
 pub async fn current_carbon_intensity() -> Result<MassPerEnergy, MetricError> {
     Ok(CarbonIntensity::try_read_from_fs().await?.get_value())
 }
 
+/// Loads the metadata of the current carbon intensity from the file system.
+/// It holds the observation time, provider, region and validity of the value.
+pub async fn current_carbon_intensity_metadata() -> Result<MetricMetadata, MetricError> {
+    CarbonIntensity::try_read_metadata_from_fs().await
+}
+
+/// Loads the current marginal carbon intensity from the file system.
+/// It gives the emissions of additional load, e.g. to decide when to run a job.
+pub async fn current_marginal_carbon_intensity() -> Result<MassPerEnergy, MetricError> {
+    Ok(MarginalCarbonIntensity::try_read_from_fs()
+        .await?
+        .get_value())
+}
+
+/// Loads the metadata of the current marginal carbon intensity from the file system.
+pub async fn current_marginal_carbon_intensity_metadata() -> Result<MetricMetadata, MetricError> {
+    MarginalCarbonIntensity::try_read_metadata_from_fs().await
+}
+
+/// Loads the current average carbon intensity from the file system.
+/// It gives the emissions attributed to consumed energy, e.g. for greenhouse gas accounting.
+pub async fn current_average_carbon_intensity() -> Result<MassPerEnergy, MetricError> {
+    Ok(AverageCarbonIntensity::try_read_from_fs()
+        .await?
+        .get_value())
+}
+
+/// Loads the metadata of the current average carbon intensity from the file system.
+pub async fn current_average_carbon_intensity_metadata() -> Result<MetricMetadata, MetricError> {
+    AverageCarbonIntensity::try_read_metadata_from_fs().await
+}
+
 /// Loads the current carbon intensity from the file system and
 /// calculates the carbon emission with a given energy.
+/// For energy consumed over a longer time, see [`crate::history::integrate_emission`].
 pub async fn calculate_carbon_emission(energy: Energy) -> Result<Mass, MetricError> {
     let carbon_intensity: MassPerEnergy = current_carbon_intensity().await?;
     Ok(carbon_intensity * energy)
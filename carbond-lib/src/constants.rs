@@ -1,15 +1,36 @@
 use const_format::concatcp;
 
+/// Default root directory of the data published by carbond.
 pub const DATA_PATH: &str = "/var/carbond";
 
+/// Environment variable overriding the root directory of the data published by carbond.
+pub const DATA_ROOT_ENV: &str = "CARBOND_DATA_ROOT";
+
 mod metric_type {
-    pub const OPERATIONAL: &str = "/operational";
-    pub const EMBODIED: &str = "/embodied";
+    pub const OPERATIONAL: &str = "operational";
+    pub const EMBODIED: &str = "embodied";
 }
 
-/// Path for storing carbon intensity
-pub const INTENSITY_PATH: &str =
-    concatcp!(DATA_PATH, metric_type::OPERATIONAL, "/carbon-intensity");
+/// Path for storing the carbon intensity of the highest priority provider regardless of its kind,
+/// relative to the data root
+pub const INTENSITY_PATH: &str = concatcp!(metric_type::OPERATIONAL, "/carbon-intensity");
+
+/// Path for storing the marginal carbon intensity, relative to the data root
+pub const MARGINAL_INTENSITY_PATH: &str =
+    concatcp!(metric_type::OPERATIONAL, "/marginal-carbon-intensity");
+
+/// Path for storing the average carbon intensity, relative to the data root
+pub const AVERAGE_INTENSITY_PATH: &str =
+    concatcp!(metric_type::OPERATIONAL, "/average-carbon-intensity");
+
+/// Path for storing the carbon intensity forecast, relative to the data root
+pub const FORECAST_PATH: &str = concatcp!(metric_type::OPERATIONAL, "/carbon-intensity-forecast");
+
+/// Path for storing the marginal health damage of the electricity, relative to the data root
+pub const HEALTH_DAMAGE_PATH: &str = concatcp!(metric_type::OPERATIONAL, "/health-damage");
+
+/// Path for storing CPU cycle intensity, relative to the data root
+pub const CPU_PATH: &str = concatcp!(metric_type::EMBODIED, "/cpu");
 
-/// Path for storing CPU cycle intensity
-pub const CPU_PATH: &str = concatcp!(DATA_PATH, metric_type::EMBODIED, "/cpu");
+/// Path of the directory storing the carbon intensity history, relative to the data root
+pub const HISTORY_PATH: &str = "history";
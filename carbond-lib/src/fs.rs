@@ -1,6 +1,28 @@
-use std::path::Path;
+use std::{
+    fs::Permissions,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
 
-use tokio::io;
+use tokio::io::{self, AsyncWriteExt};
+
+use crate::constants;
+
+/// Mode of directories created by carbond, readable by everyone but only writable by carbond.
+pub const DIR_MODE: u32 = 0o755;
+
+/// Mode of files published by carbond, readable by everyone but only writable by carbond.
+pub const FILE_MODE: u32 = 0o644;
+
+/// Root directory of the data published by carbond.
+/// Taken from `CARBOND_DATA_ROOT` and defaults to `/var/carbond`.
+pub fn data_root() -> PathBuf {
+    std::env::var_os(constants::DATA_ROOT_ENV)
+        .filter(|root| !root.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from(constants::DATA_PATH))
+}
 
 pub async fn create_file(path: &Path) -> io::Result<bool> {
     if !path.is_file() {
@@ -8,8 +30,118 @@ pub async fn create_file(path: &Path) -> io::Result<bool> {
             io::ErrorKind::NotFound,
             "Parent of path not found.",
         ))?;
-        tokio::fs::create_dir_all(prefix).await?;
+        create_dir(prefix).await?;
         return Ok(true);
     }
     Ok(false)
 }
+
+/// Creates a directory and its missing parents with [`DIR_MODE`], regardless of the umask.
+pub async fn create_dir(path: &Path) -> io::Result<()> {
+    let missing: Vec<&Path> = path.ancestors().take_while(|dir| !dir.exists()).collect();
+    for dir in missing.into_iter().rev() {
+        match tokio::fs::create_dir(dir).await {
+            Ok(()) => tokio::fs::set_permissions(dir, Permissions::from_mode(DIR_MODE)).await?,
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the content of a file so that readers either see the old or the new content.
+///
+/// The content is written to a temporary file in the same directory, flushed to disk
+/// and renamed to the target path. The directory is synced afterwards, so the rename
+/// survives a crash as well. Failing to sync the directory is not an error, as some
+/// file systems do not support it.
+pub async fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    create_file(path).await?;
+    let temp_path = temp_path(path)?;
+    let result = write_synced(&temp_path, contents.as_ref()).await;
+    let result = match result {
+        Ok(()) => tokio::fs::rename(&temp_path, path).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+        let _ = tokio::fs::remove_file(&temp_path).await;
+        return Err(e);
+    }
+    if let Some(parent) = path.parent() {
+        if let Ok(dir) = tokio::fs::File::open(parent).await {
+            let _ = dir.sync_all().await;
+        }
+    }
+    Ok(())
+}
+
+async fn write_synced(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = tokio::fs::File::create(path).await?;
+    file.set_permissions(Permissions::from_mode(FILE_MODE))
+        .await?;
+    file.write_all(contents).await?;
+    file.sync_all().await
+}
+
+/// Counts the temporary files of this process, so concurrent writes never share one.
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Hidden path next to `path` that is unique for this process and call.
+fn temp_path(path: &Path) -> io::Result<PathBuf> {
+    let name = path.file_name().ok_or(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "Path has no file name.",
+    ))?;
+    Ok(path.with_file_name(format!(
+        ".{}.{}.{}.tmp",
+        name.to_string_lossy(),
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_write_atomic_replaces_content() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("metrics").join("value");
+
+        write_atomic(&path, "old").await.unwrap();
+        write_atomic(&path, "new").await.unwrap();
+
+        assert_eq!(tokio::fs::read_to_string(&path).await.unwrap(), "new");
+        let mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&path), FILE_MODE);
+        assert_eq!(mode(path.parent().unwrap()), DIR_MODE);
+        let files: Vec<_> = std::fs::read_dir(path.parent().unwrap()).unwrap().collect();
+        assert_eq!(files.len(), 1);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_write_atomic_concurrently() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("value");
+        let contents: Vec<String> = (0..16).map(|i| i.to_string().repeat(1000)).collect();
+
+        let writes: Vec<_> = contents
+            .iter()
+            .map(|content| {
+                let path = path.clone();
+                let content = content.clone();
+                tokio::spawn(async move { write_atomic(&path, content).await })
+            })
+            .collect();
+        for write in writes {
+            write.await.unwrap().unwrap();
+        }
+
+        let written = tokio::fs::read_to_string(&path).await.unwrap();
+        assert!(contents.contains(&written));
+        let files: Vec<_> = std::fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(files.len(), 1);
+    }
+}
@@ -0,0 +1,386 @@
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use std::{
+    fs::Permissions,
+    os::unix::fs::PermissionsExt,
+    path::{Path, PathBuf},
+};
+use thiserror::Error;
+use tokio::io::AsyncWriteExt;
+use uom::si::{f64::MassPerEnergy, mass_per_energy::gram_per_kilowatt_hour};
+
+use crate::{
+    constants,
+    fs::{create_file, data_root, write_atomic, FILE_MODE},
+    metrics::forecast::{format_interval, parse_interval},
+};
+
+const RAW_FILE: &str = "raw";
+const HOURLY_FILE: &str = "hourly";
+const DAILY_FILE: &str = "daily";
+
+#[derive(Error, Debug)]
+pub enum HistoryError {
+    #[error("Could not access intensity history {0}: {1}")]
+    Io(String, #[source] std::io::Error),
+    #[error("Intensity history {0} is corrupted at line {1}.")]
+    Parse(String, usize),
+}
+
+/// Carbon intensity that was valid from `start` to `end`.
+/// Aggregated records hold the time weighted mean of an hour or day.
+#[derive(PartialEq, Debug, Clone)]
+pub struct IntensityRecord {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub intensity: MassPerEnergy,
+}
+
+/// How long records are kept in each resolution.
+/// Raw records are downsampled to hourly and hourly to daily aggregates
+/// once they are older than their retention.
+/// Daily aggregates are kept forever without a retention.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Retention {
+    pub raw: Duration,
+    pub hourly: Duration,
+    pub daily: Option<Duration>,
+}
+
+/// File based store of observed carbon intensities in raw, hourly and daily resolution.
+pub struct HistoryStore {
+    path: PathBuf,
+}
+
+/// Store in the data root given by [`data_root`].
+impl Default for HistoryStore {
+    fn default() -> Self {
+        HistoryStore::in_data_root(&data_root())
+    }
+}
+
+impl HistoryStore {
+    /// Creates a store in the given directory.
+    pub fn new(path: &Path) -> Self {
+        HistoryStore {
+            path: path.to_path_buf(),
+        }
+    }
+
+    /// Creates a store in the history directory of the given data root.
+    pub fn in_data_root(root: &Path) -> Self {
+        HistoryStore::new(&root.join(constants::HISTORY_PATH))
+    }
+
+    /// Appends a raw record to the store.
+    pub async fn append(&self, record: &IntensityRecord) -> Result<(), HistoryError> {
+        let path = self.path.join(RAW_FILE);
+        let created = create_file(&path)
+            .await
+            .map_err(|e| HistoryError::Io(RAW_FILE.to_owned(), e))?;
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .await
+            .map_err(|e| HistoryError::Io(RAW_FILE.to_owned(), e))?;
+        if created {
+            file.set_permissions(Permissions::from_mode(FILE_MODE))
+                .await
+                .map_err(|e| HistoryError::Io(RAW_FILE.to_owned(), e))?;
+        }
+        file.write_all(format_interval(record.start, record.end, record.intensity).as_bytes())
+            .await
+            .map_err(|e| HistoryError::Io(RAW_FILE.to_owned(), e))?;
+        Ok(())
+    }
+
+    /// Returns all records overlapping the time range from `start` to `end`,
+    /// ordered by their start.
+    /// Older time ranges are answered with hourly or daily aggregates.
+    pub async fn query(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<IntensityRecord>, HistoryError> {
+        let mut records = vec![];
+        for name in [DAILY_FILE, HOURLY_FILE, RAW_FILE] {
+            records.extend(
+                self.read(name)
+                    .await?
+                    .into_iter()
+                    .filter(|record| record.start < end && record.end > start),
+            );
+        }
+        records.sort_by_key(|record| record.start);
+        Ok(records)
+    }
+
+    /// Downsamples records that are older than their retention and
+    /// drops daily aggregates past their retention.
+    pub async fn compact(
+        &self,
+        now: DateTime<Utc>,
+        retention: &Retention,
+    ) -> Result<(), HistoryError> {
+        self.downsample(
+            RAW_FILE,
+            HOURLY_FILE,
+            Duration::hours(1),
+            now - retention.raw,
+        )
+        .await?;
+        self.downsample(
+            HOURLY_FILE,
+            DAILY_FILE,
+            Duration::days(1),
+            now - retention.hourly,
+        )
+        .await?;
+        if let Some(daily) = retention.daily {
+            let (_, kept) = partition_before(self.read(DAILY_FILE).await?, now - daily);
+            self.write(DAILY_FILE, &kept).await?;
+        }
+        Ok(())
+    }
+
+    /// Moves records of complete buckets that end before `cutoff` from one
+    /// resolution into aggregates of the next one.
+    async fn downsample(
+        &self,
+        from: &str,
+        to: &str,
+        bucket: Duration,
+        cutoff: DateTime<Utc>,
+    ) -> Result<(), HistoryError> {
+        let cutoff = cutoff.duration_trunc(bucket).unwrap_or(cutoff);
+        let (expired, kept) = partition_before(self.read(from).await?, cutoff);
+        if expired.is_empty() {
+            return Ok(());
+        }
+        let mut aggregates = self.read(to).await?;
+        aggregates.extend(aggregate(&expired, bucket));
+        aggregates.sort_by_key(|record| record.start);
+        self.write(to, &aggregates).await?;
+        self.write(from, &kept).await?;
+        Ok(())
+    }
+
+    async fn read(&self, name: &str) -> Result<Vec<IntensityRecord>, HistoryError> {
+        let raw = match tokio::fs::read_to_string(self.path.join(name)).await {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(e) => return Err(HistoryError::Io(name.to_owned(), e)),
+        };
+        // a last line without line break is still being appended
+        let complete = match raw.rfind('\n') {
+            Some(i) => &raw[..=i],
+            None => "",
+        };
+        complete
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim().is_empty())
+            .map(|(i, line)| {
+                let (start, end, intensity) =
+                    parse_interval(line).ok_or(HistoryError::Parse(name.to_owned(), i + 1))?;
+                Ok(IntensityRecord {
+                    start,
+                    end,
+                    intensity,
+                })
+            })
+            .collect()
+    }
+
+    async fn write(&self, name: &str, records: &[IntensityRecord]) -> Result<(), HistoryError> {
+        let content: String = records
+            .iter()
+            .map(|record| format_interval(record.start, record.end, record.intensity))
+            .collect();
+        write_atomic(&self.path.join(name), content)
+            .await
+            .map_err(|e| HistoryError::Io(name.to_owned(), e))?;
+        Ok(())
+    }
+}
+
+/// Splits records into those starting before `cutoff` and the rest.
+fn partition_before(
+    records: Vec<IntensityRecord>,
+    cutoff: DateTime<Utc>,
+) -> (Vec<IntensityRecord>, Vec<IntensityRecord>) {
+    records
+        .into_iter()
+        .partition(|record| record.start < cutoff)
+}
+
+/// Aggregates records into buckets of the given size by their start.
+/// Each aggregate holds the mean of its records weighted by their duration.
+fn aggregate(records: &[IntensityRecord], bucket: Duration) -> Vec<IntensityRecord> {
+    let mut aggregates: Vec<(DateTime<Utc>, f64, f64)> = vec![];
+    for record in records {
+        let bucket_start = record.start.duration_trunc(bucket).unwrap_or(record.start);
+        let weight = (record.end - record.start).num_seconds().max(1) as f64;
+        let value = record.intensity.get::<gram_per_kilowatt_hour>();
+        match aggregates
+            .iter_mut()
+            .find(|(start, _, _)| *start == bucket_start)
+        {
+            Some((_, sum, weights)) => {
+                *sum += value * weight;
+                *weights += weight;
+            }
+            None => aggregates.push((bucket_start, value * weight, weight)),
+        }
+    }
+    aggregates
+        .into_iter()
+        .map(|(start, sum, weights)| IntensityRecord {
+            start,
+            end: start + bucket,
+            intensity: MassPerEnergy::new::<gram_per_kilowatt_hour>(sum / weights),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use tempfile::TempDir;
+
+    fn record(hour: u32, minute: u32, minutes: i64, intensity: f64) -> IntensityRecord {
+        let start = Utc.with_ymd_and_hms(2023, 5, 1, hour, minute, 0).unwrap();
+        IntensityRecord {
+            start,
+            end: start + Duration::minutes(minutes),
+            intensity: MassPerEnergy::new::<gram_per_kilowatt_hour>(intensity),
+        }
+    }
+
+    #[test]
+    fn test_aggregate_weights_by_duration() {
+        let records = vec![
+            record(12, 0, 45, 100.0),
+            record(12, 45, 15, 300.0),
+            record(13, 0, 60, 50.0),
+        ];
+
+        let aggregates = aggregate(&records, Duration::hours(1));
+
+        assert_eq!(
+            aggregates,
+            vec![
+                IntensityRecord {
+                    start: Utc.with_ymd_and_hms(2023, 5, 1, 12, 0, 0).unwrap(),
+                    end: Utc.with_ymd_and_hms(2023, 5, 1, 13, 0, 0).unwrap(),
+                    intensity: MassPerEnergy::new::<gram_per_kilowatt_hour>(150.0),
+                },
+                record(13, 0, 60, 50.0),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_append_and_query() {
+        let dir = TempDir::new().unwrap();
+        let store = HistoryStore::new(dir.path());
+        for r in [
+            record(12, 0, 30, 100.0),
+            record(12, 30, 30, 200.0),
+            record(13, 0, 30, 300.0),
+        ] {
+            store.append(&r).await.unwrap();
+        }
+
+        let records = store
+            .query(
+                Utc.with_ymd_and_hms(2023, 5, 1, 12, 45, 0).unwrap(),
+                Utc.with_ymd_and_hms(2023, 5, 1, 14, 0, 0).unwrap(),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(
+            records,
+            vec![record(12, 30, 30, 200.0), record(13, 0, 30, 300.0)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_read_ignores_incomplete_line() {
+        let dir = TempDir::new().unwrap();
+        let store = HistoryStore::new(dir.path());
+        store.append(&record(12, 0, 30, 100.0)).await.unwrap();
+        let mut raw = tokio::fs::read_to_string(dir.path().join(RAW_FILE))
+            .await
+            .unwrap();
+        raw.push_str("2023-05-01T12:30:00Z 2023-05");
+        tokio::fs::write(dir.path().join(RAW_FILE), raw)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            store.read(RAW_FILE).await.unwrap(),
+            vec![record(12, 0, 30, 100.0)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compact() {
+        let dir = TempDir::new().unwrap();
+        let store = HistoryStore::new(dir.path());
+        for r in [
+            record(10, 0, 30, 100.0),
+            record(10, 30, 30, 200.0),
+            record(11, 0, 30, 300.0),
+            record(12, 0, 30, 400.0),
+        ] {
+            store.append(&r).await.unwrap();
+        }
+        let retention = Retention {
+            raw: Duration::hours(1),
+            hourly: Duration::days(30),
+            daily: None,
+        };
+
+        store
+            .compact(
+                Utc.with_ymd_and_hms(2023, 5, 1, 12, 30, 0).unwrap(),
+                &retention,
+            )
+            .await
+            .unwrap();
+
+        // the hour from 11:00 is not completely past the retention yet
+        assert_eq!(
+            store.read(RAW_FILE).await.unwrap(),
+            vec![record(11, 0, 30, 300.0), record(12, 0, 30, 400.0)]
+        );
+        assert_eq!(
+            store.read(HOURLY_FILE).await.unwrap(),
+            vec![record(10, 0, 60, 150.0)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compact_drops_expired_daily() {
+        let dir = TempDir::new().unwrap();
+        let store = HistoryStore::new(dir.path());
+        store.append(&record(10, 0, 30, 100.0)).await.unwrap();
+        let retention = Retention {
+            raw: Duration::hours(1),
+            hourly: Duration::hours(1),
+            daily: Some(Duration::days(1)),
+        };
+
+        let now = Utc.with_ymd_and_hms(2023, 5, 3, 0, 0, 0).unwrap();
+        store.compact(now, &retention).await.unwrap();
+
+        assert!(store
+            .query(now - Duration::days(10), now)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -1,3 +1,4 @@
 pub mod constants;
 pub mod fs;
+pub mod history;
 pub mod metrics;
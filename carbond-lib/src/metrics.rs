@@ -1,6 +1,10 @@
 pub mod carbon_intensity;
 pub mod cpu_cycles;
+pub mod forecast;
+pub mod health_damage;
+pub mod metadata;
 pub mod metric;
+pub mod parse;
 
 /// Rounds a number to 4 decimal places.
 pub(self) fn round(number: f64) -> f64 {
@@ -1,57 +1,91 @@
-use std::{num::ParseFloatError, str::FromStr};
+use std::str::FromStr;
 use uom::si::{f64::MassPerEnergy, mass_per_energy::gram_per_kilowatt_hour};
 
-use crate::{constants, metrics::round};
+use crate::{
+    constants,
+    metrics::{
+        parse::{parse_mass_per_energy, ParseError},
+        round,
+    },
+};
 
 use super::metric::Metric;
 
-/// Used to store a CarbonIntensity in the appropriate unit gram per kWh on the filesystem.
-#[derive(PartialEq, Debug)]
-pub struct CarbonIntensity {
-    mass_per_energy: MassPerEnergy,
-}
+/// Defines a carbon intensity metric stored in the appropriate unit gram per kWh on the filesystem.
+macro_rules! carbon_intensity_metric {
+    ($(#[$doc:meta])* $name:ident, $path:expr, $metric_name:literal) => {
+        $(#[$doc])*
+        #[derive(PartialEq, Debug)]
+        pub struct $name {
+            mass_per_energy: MassPerEnergy,
+        }
 
-impl ToString for CarbonIntensity {
-    fn to_string(&self) -> String {
-        let rounded_carbon_intensity = round(self.get_value().get::<gram_per_kilowatt_hour>());
-        format!("{} g/kWh", rounded_carbon_intensity)
-    }
-}
+        impl ToString for $name {
+            fn to_string(&self) -> String {
+                let rounded_carbon_intensity =
+                    round(self.get_value().get::<gram_per_kilowatt_hour>());
+                format!("{} g/kWh", rounded_carbon_intensity)
+            }
+        }
 
-impl FromStr for CarbonIntensity {
-    type Err = ParseFloatError;
+        impl FromStr for $name {
+            type Err = ParseError;
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let intensity = s.split_at(s.len() - 6).0;
-        let mass_per_energy: MassPerEnergy =
-            MassPerEnergy::new::<gram_per_kilowatt_hour>(intensity.parse()?);
-        Ok(CarbonIntensity { mass_per_energy })
-    }
-}
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                let mass_per_energy: MassPerEnergy = parse_mass_per_energy(s)?;
+                Ok($name { mass_per_energy })
+            }
+        }
 
-impl Metric for CarbonIntensity {
-    const PATH: &'static str = constants::INTENSITY_PATH;
-    const NAME: &'static str = "carbon intensity";
+        impl Metric for $name {
+            const PATH: &'static str = $path;
+            const NAME: &'static str = $metric_name;
 
-    type Unit = MassPerEnergy;
+            type Unit = MassPerEnergy;
 
-    fn neutral() -> Self {
-        CarbonIntensity {
-            mass_per_energy: MassPerEnergy::new::<gram_per_kilowatt_hour>(0.0),
-        }
-    }
+            fn neutral() -> Self {
+                $name {
+                    mass_per_energy: MassPerEnergy::new::<gram_per_kilowatt_hour>(0.0),
+                }
+            }
 
-    fn from_value(value: Self::Unit) -> Self {
-        CarbonIntensity {
-            mass_per_energy: value,
-        }
-    }
+            fn from_value(value: Self::Unit) -> Self {
+                $name {
+                    mass_per_energy: value,
+                }
+            }
 
-    fn get_value(&self) -> Self::Unit {
-        self.mass_per_energy
-    }
+            fn get_value(&self) -> Self::Unit {
+                self.mass_per_energy
+            }
+        }
+    };
 }
 
+carbon_intensity_metric!(
+    /// Carbon intensity of the highest priority provider, either marginal or average.
+    /// Prefer [`MarginalCarbonIntensity`] or [`AverageCarbonIntensity`], which state their kind.
+    CarbonIntensity,
+    constants::INTENSITY_PATH,
+    "carbon intensity"
+);
+
+carbon_intensity_metric!(
+    /// Emissions caused by consuming one more unit of energy, e.g. WattTime's MOER.
+    /// Suited for deciding when to shift a load.
+    MarginalCarbonIntensity,
+    constants::MARGINAL_INTENSITY_PATH,
+    "marginal carbon intensity"
+);
+
+carbon_intensity_metric!(
+    /// Emissions of the average unit of energy in the grid, e.g. from Electricity Maps.
+    /// Suited for accounting the emissions of consumed energy.
+    AverageCarbonIntensity,
+    constants::AVERAGE_INTENSITY_PATH,
+    "average carbon intensity"
+);
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -74,4 +108,30 @@ mod tests {
             CarbonIntensity::from_value(MassPerEnergy::new::<gram_per_kilowatt_hour>(300.54))
         );
     }
+
+    #[test]
+    fn test_from_string_incomplete() {
+        assert!(CarbonIntensity::from_str("").is_err());
+        assert!(CarbonIntensity::from_str("300").is_err());
+        assert!(CarbonIntensity::from_str("300.5 g/k").is_err());
+    }
+
+    #[test]
+    fn test_from_string_other_unit() {
+        let intensity = CarbonIntensity::from_str("1000 lb/MWh\n").unwrap();
+
+        assert!((intensity.get_value().get::<gram_per_kilowatt_hour>() - 453.5924).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_kinds_have_own_paths() {
+        assert_ne!(MarginalCarbonIntensity::PATH, AverageCarbonIntensity::PATH);
+        assert_ne!(MarginalCarbonIntensity::PATH, CarbonIntensity::PATH);
+        assert_eq!(
+            AverageCarbonIntensity::from_str("300.5 g/kWh")
+                .unwrap()
+                .to_string(),
+            "300.5 g/kWh"
+        );
+    }
 }
@@ -1,7 +1,13 @@
-use std::{num::ParseFloatError, str::FromStr};
+use std::str::FromStr;
 use uom::si::{f64::Mass, mass::picogram};
 
-use crate::{constants, metrics::round};
+use crate::{
+    constants,
+    metrics::{
+        parse::{parse_mass_per_cycle, ParseError},
+        round,
+    },
+};
 
 use super::metric::Metric;
 
@@ -19,11 +25,10 @@ impl ToString for CpuCycleIntensity {
 }
 
 impl FromStr for CpuCycleIntensity {
-    type Err = ParseFloatError;
+    type Err = ParseError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let intensity = s.split_at(s.len() - 9).0;
-        let mass: Mass = Mass::new::<picogram>(intensity.parse()?);
+        let mass: Mass = parse_mass_per_cycle(s)?;
         Ok(CpuCycleIntensity { mass })
     }
 }
@@ -70,4 +75,10 @@ mod tests {
             CpuCycleIntensity::from_value(Mass::new::<picogram>(300.54))
         );
     }
+
+    #[test]
+    fn test_from_string_incomplete() {
+        assert!(CpuCycleIntensity::from_str("").is_err());
+        assert!(CpuCycleIntensity::from_str("pg/cycle").is_err());
+    }
 }
@@ -0,0 +1,149 @@
+use chrono::{DateTime, SecondsFormat, Utc};
+use std::str::FromStr;
+use uom::si::{f64::MassPerEnergy, mass_per_energy::gram_per_kilowatt_hour};
+
+use crate::{
+    constants,
+    metrics::{parse::parse_mass_per_energy, round},
+};
+
+use super::metric::{Metric, MetricError};
+
+/// Forecasted carbon intensity for the interval from `start` to `end`.
+#[derive(PartialEq, Debug, Clone)]
+pub struct ForecastPoint {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub intensity: MassPerEnergy,
+}
+
+/// Used to store a carbon intensity forecast on the filesystem.
+/// Each line holds the start, end and intensity in gram per kWh of one interval.
+#[derive(PartialEq, Debug, Clone)]
+pub struct CarbonIntensityForecast {
+    points: Vec<ForecastPoint>,
+}
+
+impl ToString for CarbonIntensityForecast {
+    fn to_string(&self) -> String {
+        self.points
+            .iter()
+            .map(|point| format_interval(point.start, point.end, point.intensity))
+            .collect()
+    }
+}
+
+impl FromStr for CarbonIntensityForecast {
+    type Err = MetricError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let points = s
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(parse_point)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(CarbonIntensityForecast { points })
+    }
+}
+
+fn parse_point(line: &str) -> Result<ForecastPoint, MetricError> {
+    let (start, end, intensity) = parse_interval(line).ok_or(MetricError::ParseMetric(
+        CarbonIntensityForecast::NAME.to_owned(),
+    ))?;
+    Ok(ForecastPoint {
+        start,
+        end,
+        intensity,
+    })
+}
+
+/// Formats a carbon intensity valid from `start` to `end` as a line of text.
+pub(crate) fn format_interval(
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    intensity: MassPerEnergy,
+) -> String {
+    format!(
+        "{} {} {} g/kWh\n",
+        start.to_rfc3339_opts(SecondsFormat::Secs, true),
+        end.to_rfc3339_opts(SecondsFormat::Secs, true),
+        round(intensity.get::<gram_per_kilowatt_hour>())
+    )
+}
+
+/// Parses a line of text created by [`format_interval`].
+pub(crate) fn parse_interval(line: &str) -> Option<(DateTime<Utc>, DateTime<Utc>, MassPerEnergy)> {
+    let parse_time = |time: &str| {
+        DateTime::parse_from_rfc3339(time)
+            .map(|time| time.with_timezone(&Utc))
+            .ok()
+    };
+    let mut fields = line.split_whitespace();
+    let start = parse_time(fields.next()?)?;
+    let end = parse_time(fields.next()?)?;
+    let intensity = parse_mass_per_energy(&fields.collect::<Vec<_>>().join(" ")).ok()?;
+    Some((start, end, intensity))
+}
+
+impl Metric for CarbonIntensityForecast {
+    const PATH: &'static str = constants::FORECAST_PATH;
+    const NAME: &'static str = "carbon intensity forecast";
+
+    type Unit = Vec<ForecastPoint>;
+
+    fn neutral() -> Self {
+        CarbonIntensityForecast { points: vec![] }
+    }
+
+    fn from_value(value: Self::Unit) -> Self {
+        CarbonIntensityForecast { points: value }
+    }
+
+    fn get_value(&self) -> Self::Unit {
+        self.points.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn forecast() -> CarbonIntensityForecast {
+        CarbonIntensityForecast::from_value(vec![
+            ForecastPoint {
+                start: Utc.with_ymd_and_hms(2023, 5, 1, 12, 0, 0).unwrap(),
+                end: Utc.with_ymd_and_hms(2023, 5, 1, 13, 0, 0).unwrap(),
+                intensity: MassPerEnergy::new::<gram_per_kilowatt_hour>(300.5),
+            },
+            ForecastPoint {
+                start: Utc.with_ymd_and_hms(2023, 5, 1, 13, 0, 0).unwrap(),
+                end: Utc.with_ymd_and_hms(2023, 5, 1, 14, 0, 0).unwrap(),
+                intensity: MassPerEnergy::new::<gram_per_kilowatt_hour>(250.0),
+            },
+        ])
+    }
+
+    #[test]
+    fn test_to_string() {
+        assert_eq!(
+            "2023-05-01T12:00:00Z 2023-05-01T13:00:00Z 300.5 g/kWh\n\
+             2023-05-01T13:00:00Z 2023-05-01T14:00:00Z 250 g/kWh\n",
+            forecast().to_string()
+        );
+    }
+
+    #[test]
+    fn test_from_string() {
+        let parsed = CarbonIntensityForecast::from_str(&forecast().to_string());
+
+        assert_eq!(parsed.unwrap(), forecast());
+    }
+
+    #[test]
+    fn test_from_string_invalid_line() {
+        let parsed = CarbonIntensityForecast::from_str("2023-05-01T12:00:00Z 300.5 g/kWh\n");
+
+        assert!(parsed.is_err());
+    }
+}
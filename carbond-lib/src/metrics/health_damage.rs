@@ -0,0 +1,81 @@
+use std::str::FromStr;
+
+use crate::{
+    constants,
+    metrics::{
+        parse::{split_value, ParseError},
+        round,
+    },
+};
+
+use super::metric::Metric;
+
+/// Used to store the marginal health damage of the electricity in the unit reported by the provider,
+/// e.g. `12.5 $_per_mwh`. It is no carbon intensity, so it is published apart from those.
+#[derive(PartialEq, Debug)]
+pub struct HealthDamage {
+    value: f64,
+    unit: String,
+}
+
+impl ToString for HealthDamage {
+    fn to_string(&self) -> String {
+        format!("{} {}", round(self.value), self.unit)
+    }
+}
+
+impl FromStr for HealthDamage {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (value, unit) = split_value(s)?;
+        Ok(HealthDamage { value, unit })
+    }
+}
+
+impl Metric for HealthDamage {
+    const PATH: &'static str = constants::HEALTH_DAMAGE_PATH;
+    const NAME: &'static str = "health damage";
+
+    /// Value and unit of the health damage.
+    type Unit = (f64, String);
+
+    fn neutral() -> Self {
+        HealthDamage {
+            value: 0.0,
+            unit: String::new(),
+        }
+    }
+
+    fn from_value((value, unit): Self::Unit) -> Self {
+        HealthDamage { value, unit }
+    }
+
+    fn get_value(&self) -> Self::Unit {
+        (self.value, self.unit.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_string() {
+        let health_damage = HealthDamage::from_value((12.34567, "$_per_mwh".to_owned()));
+
+        assert_eq!(health_damage.to_string(), "12.3457 $_per_mwh");
+    }
+
+    #[test]
+    fn test_from_string() {
+        assert_eq!(
+            HealthDamage::from_str("12.5 $_per_mwh")
+                .unwrap()
+                .get_value(),
+            (12.5, "$_per_mwh".to_owned())
+        );
+        assert!(HealthDamage::from_str("").is_err());
+        assert!(HealthDamage::from_str("12.5").is_err());
+    }
+}
@@ -0,0 +1,202 @@
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use std::str::FromStr;
+
+use super::metric::MetricError;
+
+/// Extension of the sidecar file that holds the metadata of a metric.
+pub const METADATA_EXTENSION: &str = ".meta";
+
+/// Describes where a published metric value comes from and how long it is valid.
+#[derive(PartialEq, Debug, Clone)]
+pub struct MetricMetadata {
+    /// Time the value applies to.
+    pub observed_at: DateTime<Utc>,
+    /// Time the value was fetched or computed by carbond.
+    pub fetched_at: DateTime<Utc>,
+    /// Source of the value, e.g. the intensity provider.
+    pub provider: String,
+    pub region: Option<String>,
+    /// Time after which the value should not be used anymore.
+    pub valid_until: Option<DateTime<Utc>>,
+    /// Band the provider rates the value in, e.g. `very low`.
+    pub index: Option<String>,
+    /// Share of each fuel in the generation in percent, if the provider publishes it.
+    pub generation_mix: Vec<(String, f64)>,
+}
+
+impl MetricMetadata {
+    /// Time passed since the value was observed.
+    pub fn age(&self) -> Duration {
+        Utc::now() - self.observed_at
+    }
+
+    /// Checks if the value is past its validity.
+    /// Values without a validity never become stale.
+    pub fn is_stale(&self) -> bool {
+        self.is_stale_at(Utc::now())
+    }
+
+    /// Checks if the value is past its validity at the given time.
+    pub fn is_stale_at(&self, time: DateTime<Utc>) -> bool {
+        self.valid_until
+            .map(|valid_until| time > valid_until)
+            .unwrap_or(false)
+    }
+}
+
+impl ToString for MetricMetadata {
+    fn to_string(&self) -> String {
+        let mut lines = vec![
+            format!("observed_at={}", format_time(&self.observed_at)),
+            format!("fetched_at={}", format_time(&self.fetched_at)),
+            format!("provider={}", self.provider),
+        ];
+        if let Some(region) = &self.region {
+            lines.push(format!("region={region}"));
+        }
+        if let Some(valid_until) = &self.valid_until {
+            lines.push(format!("valid_until={}", format_time(valid_until)));
+        }
+        if let Some(index) = &self.index {
+            lines.push(format!("index={index}"));
+        }
+        if !self.generation_mix.is_empty() {
+            let mix: Vec<String> = self
+                .generation_mix
+                .iter()
+                .map(|(fuel, share)| format!("{fuel}:{share}"))
+                .collect();
+            lines.push(format!("generation_mix={}", mix.join(",")));
+        }
+        lines.join("\n") + "\n"
+    }
+}
+
+impl FromStr for MetricMetadata {
+    type Err = MetricError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut observed_at = None;
+        let mut fetched_at = None;
+        let mut provider = None;
+        let mut region = None;
+        let mut valid_until = None;
+        let mut index = None;
+        let mut generation_mix = vec![];
+        for line in s.lines().map(str::trim).filter(|line| !line.is_empty()) {
+            let (key, value) = line.split_once('=').ok_or_else(parse_error)?;
+            match key.trim() {
+                "observed_at" => observed_at = Some(parse_time(value)?),
+                "fetched_at" => fetched_at = Some(parse_time(value)?),
+                "provider" => provider = Some(value.trim().to_owned()),
+                "region" => region = Some(value.trim().to_owned()),
+                "valid_until" => valid_until = Some(parse_time(value)?),
+                "index" => index = Some(value.trim().to_owned()),
+                "generation_mix" => generation_mix = parse_generation_mix(value)?,
+                // ignore unknown keys of newer versions
+                _ => {}
+            }
+        }
+        Ok(MetricMetadata {
+            observed_at: observed_at.ok_or_else(parse_error)?,
+            fetched_at: fetched_at.ok_or_else(parse_error)?,
+            provider: provider.ok_or_else(parse_error)?,
+            region,
+            valid_until,
+            index,
+            generation_mix,
+        })
+    }
+}
+
+fn format_time(time: &DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Secs, true)
+}
+
+fn parse_time(value: &str) -> Result<DateTime<Utc>, MetricError> {
+    DateTime::parse_from_rfc3339(value.trim())
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| parse_error())
+}
+
+/// Parses a generation mix like `gas:20.5,wind:30`.
+fn parse_generation_mix(value: &str) -> Result<Vec<(String, f64)>, MetricError> {
+    value
+        .split(',')
+        .map(|entry| {
+            let (fuel, share) = entry.split_once(':').ok_or_else(parse_error)?;
+            let share = share.trim().parse().map_err(|_| parse_error())?;
+            Ok((fuel.trim().to_owned(), share))
+        })
+        .collect()
+}
+
+fn parse_error() -> MetricError {
+    MetricError::ParseMetric("metadata".to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn metadata() -> MetricMetadata {
+        MetricMetadata {
+            observed_at: Utc.with_ymd_and_hms(2023, 5, 1, 12, 0, 0).unwrap(),
+            fetched_at: Utc.with_ymd_and_hms(2023, 5, 1, 12, 0, 3).unwrap(),
+            provider: "watt_time".to_owned(),
+            region: Some("CAISO_NORTH".to_owned()),
+            valid_until: Some(Utc.with_ymd_and_hms(2023, 5, 1, 12, 5, 0).unwrap()),
+            index: None,
+            generation_mix: vec![],
+        }
+    }
+
+    #[test]
+    fn test_to_string() {
+        assert_eq!(
+            "observed_at=2023-05-01T12:00:00Z\n\
+             fetched_at=2023-05-01T12:00:03Z\n\
+             provider=watt_time\n\
+             region=CAISO_NORTH\n\
+             valid_until=2023-05-01T12:05:00Z\n",
+            metadata().to_string()
+        );
+    }
+
+    #[test]
+    fn test_from_string() {
+        let parsed = MetricMetadata::from_str(&metadata().to_string()).unwrap();
+
+        assert_eq!(metadata(), parsed);
+    }
+
+    #[test]
+    fn test_index_and_generation_mix() {
+        let metadata = MetricMetadata {
+            index: Some("very low".to_owned()),
+            generation_mix: vec![("gas".to_owned(), 20.5), ("wind".to_owned(), 30.0)],
+            ..metadata()
+        };
+
+        let text = metadata.to_string();
+
+        assert!(text.ends_with("index=very low\ngeneration_mix=gas:20.5,wind:30\n"));
+        assert_eq!(MetricMetadata::from_str(&text).unwrap(), metadata);
+    }
+
+    #[test]
+    fn test_from_string_missing_key() {
+        let parsed = MetricMetadata::from_str("provider=watt_time\n");
+
+        assert!(parsed.is_err());
+    }
+
+    #[test]
+    fn test_is_stale_at() {
+        let metadata = metadata();
+
+        assert!(!metadata.is_stale_at(Utc.with_ymd_and_hms(2023, 5, 1, 12, 4, 0).unwrap()));
+        assert!(metadata.is_stale_at(Utc.with_ymd_and_hms(2023, 5, 1, 12, 6, 0).unwrap()));
+    }
+}
@@ -1,9 +1,15 @@
 use async_trait::async_trait;
 use log::debug;
-use std::{path::Path, str::FromStr};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
 use thiserror::Error;
 
-use crate::fs::create_file;
+use crate::fs::{data_root, write_atomic};
+
+use super::metadata::{MetricMetadata, METADATA_EXTENSION};
 
 #[derive(Error, Debug)]
 pub enum MetricError {
@@ -11,10 +17,17 @@ pub enum MetricError {
     WriteMetric(String),
     #[error("Could not read {0} from fs.")]
     ReadMetric(String),
+    #[error("Metric {0} has not been published yet.")]
+    MissingMetric(String),
     #[error("Format of metric {0} is invalid on fs.")]
     ParseMetric(String),
 }
 
+/// How often a metric that cannot be parsed is read again.
+/// Metrics are replaced atomically, but files of older writers may be read while being written.
+const READ_ATTEMPTS: u32 = 3;
+const READ_RETRY_DELAY: Duration = Duration::from_millis(20);
+
 #[async_trait]
 pub trait Metric: FromStr + ToString {
     type Unit;
@@ -25,27 +38,166 @@ pub trait Metric: FromStr + ToString {
     fn neutral() -> Self;
     fn from_value(value: Self::Unit) -> Self;
 
-    /// Reads and parses the corresponding metric value from the file system.
+    /// Reads and parses the corresponding metric value from the data root given by [`data_root`].
     async fn try_read_from_fs() -> Result<Self, MetricError> {
-        let file_path = Path::new(Self::PATH);
-        let raw = tokio::fs::read_to_string(file_path)
-            .await
-            .map_err(|_| MetricError::ReadMetric(Self::NAME.to_owned()))?;
-        let instance =
-            Self::from_str(&raw).map_err(|_| MetricError::ParseMetric(Self::NAME.to_owned()))?;
-        Ok(instance)
+        Self::try_read_from_dir(&data_root()).await
     }
 
-    /// Writes the metric value in a readable format to the file system.
+    /// Reads and parses the corresponding metric value from the given data root.
+    async fn try_read_from_dir(root: &Path) -> Result<Self, MetricError> {
+        read_from_fs(&root.join(Self::PATH), Self::NAME).await
+    }
+
+    /// Writes the metric value in a readable format to the data root given by [`data_root`].
     async fn try_write_to_fs(&self) -> Result<(), MetricError> {
-        let file_path = Path::new(Self::PATH);
+        self.try_write_to_dir(&data_root()).await
+    }
+
+    /// Writes the metric value in a readable format to the given data root.
+    async fn try_write_to_dir(&self, root: &Path) -> Result<(), MetricError> {
+        let file_path = root.join(Self::PATH);
         debug!("Write {:#?} to {:#?}", self.to_string(), file_path);
-        create_file(file_path)
-            .await
-            .map_err(|_| MetricError::WriteMetric(Self::NAME.to_owned()))?;
-        tokio::fs::write(file_path, self.to_string())
+        write_atomic(&file_path, self.to_string())
             .await
             .map_err(|_| MetricError::WriteMetric(Self::NAME.to_owned()))?;
         Ok(())
     }
+
+    /// Writes the metric value and its metadata to the data root given by [`data_root`].
+    async fn try_write_to_fs_with_metadata(
+        &self,
+        metadata: &MetricMetadata,
+    ) -> Result<(), MetricError> {
+        self.try_write_to_dir_with_metadata(&data_root(), metadata)
+            .await
+    }
+
+    /// Writes the metric value and its metadata to the given data root.
+    /// The metadata is stored in a sidecar file next to the value.
+    ///
+    /// Both files are replaced atomically but one after the other. The metadata is written
+    /// first, so a new value is never published with the metadata of the previous one and a
+    /// failed metadata write keeps the previous value. In between, readers may see the new
+    /// metadata next to the previous value.
+    async fn try_write_to_dir_with_metadata(
+        &self,
+        root: &Path,
+        metadata: &MetricMetadata,
+    ) -> Result<(), MetricError> {
+        let metadata_path = metadata_path(root, Self::PATH);
+        debug!("Write {:#?} to {:#?}", metadata, metadata_path);
+        write_atomic(&metadata_path, metadata.to_string())
+            .await
+            .map_err(|_| MetricError::WriteMetric(format!("{} metadata", Self::NAME)))?;
+        self.try_write_to_dir(root).await
+    }
+
+    /// Reads and parses the metadata of the metric from the data root given by [`data_root`].
+    async fn try_read_metadata_from_fs() -> Result<MetricMetadata, MetricError> {
+        Self::try_read_metadata_from_dir(&data_root()).await
+    }
+
+    /// Reads and parses the metadata of the metric from the given data root.
+    async fn try_read_metadata_from_dir(root: &Path) -> Result<MetricMetadata, MetricError> {
+        read_from_fs(
+            &metadata_path(root, Self::PATH),
+            &format!("{} metadata", Self::NAME),
+        )
+        .await
+    }
+}
+
+fn metadata_path(root: &Path, path: &str) -> PathBuf {
+    root.join(format!("{path}{METADATA_EXTENSION}"))
+}
+
+/// Reads and parses a metric file.
+/// Rereads the file a few times if it cannot be parsed.
+async fn read_from_fs<T: FromStr>(path: &Path, name: &str) -> Result<T, MetricError> {
+    let mut attempt = 1;
+    loop {
+        let raw = match tokio::fs::read_to_string(path).await {
+            Ok(raw) => raw,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Err(MetricError::MissingMetric(name.to_owned()))
+            }
+            Err(_) => return Err(MetricError::ReadMetric(name.to_owned())),
+        };
+        if let Ok(instance) = T::from_str(&raw) {
+            return Ok(instance);
+        }
+        if attempt >= READ_ATTEMPTS {
+            return Err(MetricError::ParseMetric(name.to_owned()));
+        }
+        attempt += 1;
+        tokio::time::sleep(READ_RETRY_DELAY).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::carbon_intensity::CarbonIntensity;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_read_from_fs() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("carbon-intensity");
+        tokio::fs::write(&path, "300.5 g/kWh").await.unwrap();
+
+        let metric: CarbonIntensity = read_from_fs(&path, "carbon intensity").await.unwrap();
+
+        assert_eq!(metric.to_string(), "300.5 g/kWh");
+    }
+
+    #[tokio::test]
+    async fn test_write_to_dir_with_metadata() {
+        let dir = TempDir::new().unwrap();
+        let metadata: MetricMetadata =
+            "observed_at=2023-05-01T12:00:00Z\nfetched_at=2023-05-01T12:01:00Z\nprovider=test\n"
+                .parse()
+                .unwrap();
+        let metric = CarbonIntensity::from_str("300.5 g/kWh").unwrap();
+
+        metric
+            .try_write_to_dir_with_metadata(dir.path(), &metadata)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            CarbonIntensity::try_read_from_dir(dir.path())
+                .await
+                .unwrap(),
+            metric
+        );
+        assert_eq!(
+            CarbonIntensity::try_read_metadata_from_dir(dir.path())
+                .await
+                .unwrap(),
+            metadata
+        );
+        assert!(dir.path().join("operational/carbon-intensity").is_file());
+    }
+
+    #[tokio::test]
+    async fn test_read_from_fs_missing() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("carbon-intensity");
+
+        let result = read_from_fs::<CarbonIntensity>(&path, "carbon intensity").await;
+
+        assert!(matches!(result, Err(MetricError::MissingMetric(_))));
+    }
+
+    #[tokio::test]
+    async fn test_read_from_fs_incomplete() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("carbon-intensity");
+        tokio::fs::write(&path, "").await.unwrap();
+
+        let result = read_from_fs::<CarbonIntensity>(&path, "carbon intensity").await;
+
+        assert!(matches!(result, Err(MetricError::ParseMetric(_))));
+    }
 }
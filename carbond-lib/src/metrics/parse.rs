@@ -0,0 +1,155 @@
+use thiserror::Error;
+use uom::si::{
+    energy::{gigawatt_hour, joule, kilojoule, kilowatt_hour, megajoule, megawatt_hour, watt_hour},
+    f64::{Energy, Mass, MassPerEnergy},
+    mass::{gram, kilogram, microgram, milligram, nanogram, picogram, pound, ton},
+    mass_per_energy::gram_per_kilowatt_hour,
+};
+
+#[derive(Error, Debug, PartialEq)]
+pub enum ParseError {
+    #[error("Metric value is empty.")]
+    Empty,
+    #[error("{0} is not a valid number.")]
+    InvalidNumber(String),
+    #[error("Metric value has no unit.")]
+    MissingUnit,
+    #[error("Unit {0} is not supported.")]
+    UnsupportedUnit(String),
+}
+
+/// Parses a carbon intensity like `300.5 g/kWh`.
+/// Accepts masses from `pg` to `t` and `lb` per energies from `J` to `GWh`,
+/// optionally followed by the emitted gas, e.g. `lbs CO2/MWh` or `gCO2eq/kWh`.
+pub fn parse_mass_per_energy(s: &str) -> Result<MassPerEnergy, ParseError> {
+    let (value, unit) = split_value(s)?;
+    let (mass_unit, energy_unit) = unit
+        .split_once('/')
+        .ok_or(ParseError::UnsupportedUnit(unit.to_owned()))?;
+    let mass = mass(mass_unit).ok_or(ParseError::UnsupportedUnit(unit.to_owned()))?;
+    let energy = energy(energy_unit).ok_or(ParseError::UnsupportedUnit(unit.to_owned()))?;
+    // convert to the unit stored by carbond first to keep its values exact
+    Ok(MassPerEnergy::new::<gram_per_kilowatt_hour>(
+        value * mass.get::<gram>() / energy.get::<kilowatt_hour>(),
+    ))
+}
+
+/// Parses a carbon emission per cpu cycle like `300.5 pg/cycle`.
+pub fn parse_mass_per_cycle(s: &str) -> Result<Mass, ParseError> {
+    let (value, unit) = split_value(s)?;
+    match unit.split_once('/') {
+        Some((mass_unit, "cycle" | "cycles")) => {
+            let mass = mass(mass_unit).ok_or(ParseError::UnsupportedUnit(unit.to_owned()))?;
+            Ok(Mass::new::<picogram>(value * mass.get::<picogram>()))
+        }
+        _ => Err(ParseError::UnsupportedUnit(unit.to_owned())),
+    }
+}
+
+/// Splits a text into its number and unit.
+/// The unit may follow the number with or without whitespace.
+pub(crate) fn split_value(s: &str) -> Result<(f64, String), ParseError> {
+    let s = s.trim();
+    if s.is_empty() {
+        return Err(ParseError::Empty);
+    }
+    let unit_start = s
+        .find(|c: char| !(c.is_ascii_digit() || matches!(c, '.' | '-' | '+' | 'e' | 'E')))
+        .ok_or(ParseError::MissingUnit)?;
+    let (number, unit) = s.split_at(unit_start);
+    let value = number
+        .parse()
+        .map_err(|_| ParseError::InvalidNumber(number.to_owned()))?;
+    // units are compared without whitespace, e.g. `lbs CO2 / MWh`
+    let unit: String = unit.split_whitespace().collect();
+    if unit.is_empty() {
+        return Err(ParseError::MissingUnit);
+    }
+    Ok((value, unit))
+}
+
+/// Mass of one given unit.
+fn mass(unit: &str) -> Option<Mass> {
+    let unit = ["CO2eq", "CO2e", "CO2"]
+        .iter()
+        .find_map(|gas| unit.strip_suffix(gas))
+        .unwrap_or(unit);
+    let mass = match unit {
+        "pg" => Mass::new::<picogram>(1.0),
+        "ng" => Mass::new::<nanogram>(1.0),
+        "µg" | "ug" => Mass::new::<microgram>(1.0),
+        "mg" => Mass::new::<milligram>(1.0),
+        "g" => Mass::new::<gram>(1.0),
+        "kg" => Mass::new::<kilogram>(1.0),
+        "t" => Mass::new::<ton>(1.0),
+        "lb" | "lbs" => Mass::new::<pound>(1.0),
+        _ => return None,
+    };
+    Some(mass)
+}
+
+/// Energy of one given unit.
+fn energy(unit: &str) -> Option<Energy> {
+    let energy = match unit {
+        "J" => Energy::new::<joule>(1.0),
+        "kJ" => Energy::new::<kilojoule>(1.0),
+        "MJ" => Energy::new::<megajoule>(1.0),
+        "Wh" => Energy::new::<watt_hour>(1.0),
+        "kWh" => Energy::new::<kilowatt_hour>(1.0),
+        "MWh" => Energy::new::<megawatt_hour>(1.0),
+        "GWh" => Energy::new::<gigawatt_hour>(1.0),
+        _ => return None,
+    };
+    Some(energy)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grams_per_kwh(s: &str) -> f64 {
+        parse_mass_per_energy(s)
+            .unwrap()
+            .get::<gram_per_kilowatt_hour>()
+    }
+
+    #[test]
+    fn test_parse_mass_per_energy() {
+        assert!((grams_per_kwh("300.5 g/kWh") - 300.5).abs() < 1e-9);
+        assert!((grams_per_kwh("300.5 g/kWh\n") - 300.5).abs() < 1e-9);
+        assert!((grams_per_kwh("300.5g/kWh") - 300.5).abs() < 1e-9);
+        assert!((grams_per_kwh("300.5 kg/MWh") - 300.5).abs() < 1e-9);
+        assert!((grams_per_kwh("1000 lb/MWh") - 453.5924).abs() < 1e-9);
+        assert!((grams_per_kwh("1000 lbs CO2/MWh") - 453.5924).abs() < 1e-9);
+        assert!((grams_per_kwh("300 gCO2eq/kWh") - 300.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_parse_mass_per_cycle() {
+        let mass = parse_mass_per_cycle("1 g/cycle").unwrap();
+        assert!((mass.get::<gram>() - 1.0).abs() < 1e-9);
+        let mass = parse_mass_per_cycle("300 ng/cycle").unwrap();
+        assert!((mass.get::<picogram>() - 300_000.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(parse_mass_per_energy(""), Err(ParseError::Empty));
+        assert_eq!(
+            parse_mass_per_energy("  300 "),
+            Err(ParseError::MissingUnit)
+        );
+        assert_eq!(
+            parse_mass_per_energy("3.0.0 g/kWh"),
+            Err(ParseError::InvalidNumber("3.0.0".to_owned()))
+        );
+        assert_eq!(
+            parse_mass_per_energy("300 g/k"),
+            Err(ParseError::UnsupportedUnit("g/k".to_owned()))
+        );
+        assert_eq!(
+            parse_mass_per_cycle("300 g/kWh"),
+            Err(ParseError::UnsupportedUnit("g/kWh".to_owned()))
+        );
+    }
+}
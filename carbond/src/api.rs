@@ -1,132 +1,593 @@
-use crate::{data, errors::APIError};
-use log::debug;
-use reqwest::{Response, StatusCode};
-use uom::si::{f64::MassPerEnergy, mass_per_energy::pound_per_megawatt_hour};
+use std::{fmt::Display, future::Future, pin::Pin};
 
-#[derive(Debug)]
-pub struct Unauthorized;
+use async_trait::async_trait;
+use carbond_lib::metrics::{forecast::ForecastPoint, metadata::MetricMetadata};
+use chrono::{DateTime, Duration, Utc};
+use log::{info, warn};
+use uom::si::f64::MassPerEnergy;
+
+use crate::{
+    data::config::internal::{Config, ProviderKind, SignalType},
+    errors::{APIError, CarbondError, ConfigError},
+};
+
+pub(crate) mod electricity_map;
+pub(crate) mod national_grid;
+pub(crate) mod watt_time;
+
+/// A source of carbon intensity values, e.g. WattTime or Electricity Maps.
+#[async_trait]
+pub(crate) trait IntensityProvider: Send + Sync {
+    /// Name of the provider as used in the config.
+    fn name(&self) -> &'static str;
+
+    /// Kind of the carbon intensity the provider reports.
+    fn kind(&self) -> IntensityKind;
+
+    /// Requests the current carbon intensity from the provider.
+    async fn carbon_intensity(&self) -> Result<Observation, APIError>;
+
+    /// Requests the forecasted carbon intensity from the provider.
+    async fn carbon_intensity_forecast(&self) -> Result<Forecast, APIError>;
+}
+
+/// Whether a carbon intensity covers additional load or the average load of the grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) enum IntensityKind {
+    Marginal,
+    Average,
+}
+
+impl Display for IntensityKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IntensityKind::Marginal => write!(f, "marginal"),
+            IntensityKind::Average => write!(f, "average"),
+        }
+    }
+}
+
+/// Carbon intensity as reported by a provider.
+#[derive(Debug, Clone)]
+pub(crate) struct Observation {
+    pub intensity: MassPerEnergy,
+    /// Time the intensity applies to.
+    pub observed_at: DateTime<Utc>,
+    pub region: String,
+    /// Time until the provider publishes the next value.
+    pub valid_until: Option<DateTime<Utc>>,
+    /// Percentile of the intensity compared to the upcoming day, if the provider reports it.
+    pub percentile: Option<f64>,
+    /// Band the provider rates the intensity in, if it reports one.
+    pub index: Option<IntensityIndex>,
+    /// Share of each fuel in the generation in percent, if the provider reports it.
+    pub generation_mix: Vec<(String, f64)>,
+}
 
+/// Band of a carbon intensity as rated by a provider.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum IntensityIndex {
+    VeryLow,
+    Low,
+    Moderate,
+    High,
+    VeryHigh,
+}
+
+impl Display for IntensityIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IntensityIndex::VeryLow => write!(f, "very low"),
+            IntensityIndex::Low => write!(f, "low"),
+            IntensityIndex::Moderate => write!(f, "moderate"),
+            IntensityIndex::High => write!(f, "high"),
+            IntensityIndex::VeryHigh => write!(f, "very high"),
+        }
+    }
+}
+
+/// Carbon intensity forecast as reported by a provider.
+#[derive(Debug, Clone)]
+pub(crate) struct Forecast {
+    pub points: Vec<ForecastPoint>,
+    /// Time the forecast was generated by the provider.
+    pub generated_at: DateTime<Utc>,
+    pub region: String,
+}
+
+/// Value of a signal that is no carbon intensity, in the unit reported by the provider.
+#[derive(Debug, Clone)]
+pub(crate) struct SignalValue {
+    pub value: f64,
+    pub unit: String,
+    /// Time the value applies to.
+    pub observed_at: DateTime<Utc>,
+    pub region: String,
+    /// Time until the provider publishes the next value.
+    pub valid_until: Option<DateTime<Utc>>,
+}
+
+/// A value together with the provider that supplied it.
 #[derive(Debug)]
-pub struct Authorized {
-    token: String,
+pub(crate) struct Provided<T> {
+    pub value: T,
+    pub provider: &'static str,
+    pub fetched_at: DateTime<Utc>,
 }
 
-pub(crate) struct Api<State = Unauthorized> {
-    username: String,
-    password: String,
-    region: String,
-    state: State,
+impl Provided<Observation> {
+    /// Metadata to publish alongside the carbon intensity.
+    pub fn metadata(&self) -> MetricMetadata {
+        MetricMetadata {
+            observed_at: self.value.observed_at,
+            fetched_at: self.fetched_at,
+            provider: self.provider.to_owned(),
+            region: Some(self.value.region.clone()),
+            valid_until: self.value.valid_until,
+            index: self.value.index.map(|index| index.to_string()),
+            generation_mix: self.value.generation_mix.clone(),
+        }
+    }
 }
 
-const INVALID_REGION_TEXT: &str = "You requested data for an unrecognized ba";
+impl Provided<SignalValue> {
+    /// Metadata to publish alongside the signal value.
+    pub fn metadata(&self) -> MetricMetadata {
+        MetricMetadata {
+            observed_at: self.value.observed_at,
+            fetched_at: self.fetched_at,
+            provider: self.provider.to_owned(),
+            region: Some(self.value.region.clone()),
+            valid_until: self.value.valid_until,
+            index: None,
+            generation_mix: vec![],
+        }
+    }
+}
 
-impl Api<Unauthorized> {
-    /// creates a new unauthorized instance of the API
-    pub fn new(username: &str, password: &str, region: &str) -> Api {
-        Self {
-            username: String::from(username),
-            password: String::from(password),
-            region: String::from(region),
-            state: Unauthorized,
+impl Provided<Forecast> {
+    /// Metadata to publish alongside the forecast.
+    /// The forecast is valid until its last interval ends.
+    pub fn metadata(&self) -> MetricMetadata {
+        MetricMetadata {
+            observed_at: self.value.generated_at,
+            fetched_at: self.fetched_at,
+            provider: self.provider.to_owned(),
+            region: Some(self.value.region.clone()),
+            valid_until: self.value.points.last().map(|point| point.end),
+            index: None,
+            generation_mix: vec![],
         }
     }
+}
 
-    /// performs a login attempt to obtain a token from the API.
-    pub async fn login(&self) -> Result<Api<Authorized>, APIError> {
-        debug!("Request login to wattime API.");
-        let client = reqwest::Client::new();
-        let response = client
-            .get("https://api2.watttime.org/v2/login")
-            .basic_auth(&self.username, Some(&self.password))
-            .send()
-            .await;
-
-        // handle request errors
-        let response = match response {
-            Ok(res) => {
-                if res.status() == StatusCode::FORBIDDEN {
-                    return Err(APIError::InvalidCredentials);
+/// Creates the intensity providers in the order of the configured priority.
+/// WattTime logs in with the cached token of previous requests and is skipped if it reports
+/// the health damage, which is no carbon intensity.
+pub(crate) fn providers(
+    config: &Config,
+    watt_time_token: &watt_time::TokenCache,
+) -> Result<Vec<Box<dyn IntensityProvider>>, CarbondError> {
+    let mut providers: Vec<Box<dyn IntensityProvider>> = vec![];
+    for kind in &config.provider_priority {
+        match kind {
+            ProviderKind::WattTime => {
+                if let Some(wt) = config
+                    .watt_time
+                    .as_ref()
+                    .filter(|wt| wt.signal_type != SignalType::HealthDamage)
+                {
+                    providers.push(Box::new(watt_time::Api::new(
+                        config.http.client.clone(),
+                        wt,
+                        watt_time_token.clone(),
+                    )));
                 }
-                res
             }
-            Err(e) => {
-                return Err(APIError::Unhandled(e.to_string()));
+            ProviderKind::ElectricityMap => {
+                if let Some(em) = config.electricity_map.as_ref() {
+                    providers.push(Box::new(electricity_map::Api::new(
+                        config.http.client.clone(),
+                        &em.base_url,
+                        em.token.expose(),
+                        &em.region,
+                    )));
+                }
             }
-        };
-
-        // handle body parsing errors
-        let response: data::api::WattTimeLoginResponse = match response.json().await {
-            Ok(res) => res,
-            Err(e) => return Err(APIError::Deserialze(e.to_string())),
-        };
-        let api_authorized = Api {
-            username: self.username.clone(),
-            password: self.password.clone(),
-            region: self.region.clone(),
-            state: Authorized {
-                token: response.token,
-            },
-        };
-        debug!("Logged in successfully.");
-        Ok(api_authorized)
+            ProviderKind::NationalGrid => {
+                if let Some(ng) = config.national_grid.as_ref() {
+                    providers.push(Box::new(national_grid::Api::new(
+                        config.http.client.clone(),
+                        &ng.base_url,
+                        &ng.region,
+                    )));
+                }
+            }
+        }
     }
+    if providers.is_empty() {
+        return Err(CarbondError::Config(ConfigError::ConfigMissing(
+            String::from("A config for watt_time, electricity_map or national_grid is missing"),
+        )));
+    }
+    Ok(providers)
 }
 
-impl Api<Authorized> {
-    /// Requests carbon intensity from WattTime.
-    pub async fn get_watt_time_moer(&self) -> Result<MassPerEnergy, APIError> {
-        let response = self.request_watt_time().await?;
-        // WattTime returns a moer in CO2 lbs/MWh
-        let moer: f64 = response.moer.parse().map_err(|_| {
-            APIError::Unhandled("Moer value from API could not be parsed to f64.".to_owned())
-        })?;
-        let carbon_intensity: MassPerEnergy = MassPerEnergy::new::<pound_per_megawatt_hour>(moer);
-        Ok(carbon_intensity)
+/// Requests the carbon intensity of every kind offered by the providers.
+/// Kinds are requested in the order their first provider appears in, and only fall back
+/// to providers of the same kind. Kinds in `skip` are not requested.
+pub(crate) async fn request_carbon_intensities(
+    providers: &[Box<dyn IntensityProvider>],
+    skip: &[IntensityKind],
+) -> Vec<(IntensityKind, Result<Provided<Observation>, APIError>)> {
+    let mut kinds: Vec<IntensityKind> = vec![];
+    for provider in providers {
+        if !kinds.contains(&provider.kind()) && !skip.contains(&provider.kind()) {
+            kinds.push(provider.kind());
+        }
     }
+    let mut results = vec![];
+    for kind in kinds {
+        let of_kind: Vec<_> = providers
+            .iter()
+            .filter(|provider| provider.kind() == kind)
+            .collect();
+        let result = request_with_fallback(of_kind, |provider| provider.carbon_intensity()).await;
+        results.push((kind, result));
+    }
+    results
+}
 
-    async fn request_watt_time(&self) -> Result<data::api::WattTimeResponse, APIError> {
-        debug!("Requesting moer for region {} from wattime.", self.region);
-        let client = reqwest::Client::new();
-        let url = format!(
-            "https://api2.watttime.org/v2/index?ba={}&style=moer",
-            self.region
-        );
-        let response = client.get(url).bearer_auth(&self.state.token).send().await;
-        // handle request errors
-        let response = match response {
-            Ok(res) => {
-                if let StatusCode::OK = res.status() {
-                    res
-                } else {
-                    return Err(self.handle_error(res).await);
-                }
+/// Requests the carbon intensity forecast from the providers in the given order.
+/// Falls back to the next provider of the same kind as the first one if a request fails
+/// with an error that allows it, so the forecast always has the kind of the primary intensity.
+pub(crate) async fn request_carbon_intensity_forecast(
+    providers: &[Box<dyn IntensityProvider>],
+) -> Result<Provided<Forecast>, APIError> {
+    let primary_kind = providers.first().map(|provider| provider.kind());
+    let of_kind: Vec<_> = providers
+        .iter()
+        .filter(|provider| Some(provider.kind()) == primary_kind)
+        .collect();
+    request_with_fallback(of_kind, |provider| provider.carbon_intensity_forecast()).await
+}
+
+type ProviderFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, APIError>> + Send + 'a>>;
+
+async fn request_with_fallback<'a, T, F>(
+    providers: impl IntoIterator<Item = &'a Box<dyn IntensityProvider>>,
+    request: F,
+) -> Result<Provided<T>, APIError>
+where
+    F: Fn(&'a dyn IntensityProvider) -> ProviderFuture<'a, T>,
+{
+    let mut last_error = APIError::Unhandled(String::from("No intensity provider configured"));
+    for provider in providers {
+        match request(provider.as_ref()).await {
+            Ok(value) => {
+                info!("Carbon intensity supplied by {}.", provider.name());
+                return Ok(Provided {
+                    value,
+                    provider: provider.name(),
+                    fetched_at: Utc::now(),
+                });
+            }
+            Err(e) if e.allows_fallback() => {
+                warn!("Intensity provider {} failed: {e}", provider.name());
+                last_error = e;
             }
-            Err(e) => {
-                return Err(APIError::Unhandled(e.to_string()));
+            Err(e) => return Err(e),
+        }
+    }
+    Err(last_error)
+}
+
+/// Turns the intensities at the start of consecutive intervals into forecast points.
+/// Each interval ends where the next one starts, the last one lasts as long as its
+/// predecessor or `default_period` if there is only one.
+fn to_forecast_points(
+    starts: Vec<(DateTime<Utc>, MassPerEnergy)>,
+    default_period: Duration,
+) -> Vec<ForecastPoint> {
+    let mut points: Vec<ForecastPoint> = Vec::with_capacity(starts.len());
+    for (i, (start, intensity)) in starts.iter().enumerate() {
+        let end = match starts.get(i + 1) {
+            Some((next_start, _)) => *next_start,
+            None => {
+                let period = points
+                    .last()
+                    .map(|previous| previous.end - previous.start)
+                    .unwrap_or(default_period);
+                *start + period
             }
         };
-        // handle body parsing errors
-        let response: data::api::WattTimeResponse = response
-            .json()
-            .await
-            .map_err(|op| APIError::Deserialze(op.to_string()))?;
-        debug!("Got {:#?}.", response);
-        Ok(response)
-    }
-
-    async fn handle_error(&self, response: Response) -> APIError {
-        match response.status() {
-            StatusCode::BAD_REQUEST => {
-                let response: data::api::WattTimeError = match response.json().await {
-                    Ok(res) => res,
-                    Err(e) => return APIError::Deserialze(e.to_string()),
-                };
-                match response.message.as_str() {
-                    INVALID_REGION_TEXT => APIError::InvalidRegion(self.region.clone()),
-                    _ => APIError::Unhandled(response.message),
+        points.push(ForecastPoint {
+            start: *start,
+            end,
+            intensity: *intensity,
+        });
+    }
+    points
+}
+
+/// Maps an error of a failed request to an [`APIError`].
+fn request_error(error: reqwest::Error) -> APIError {
+    if error.is_timeout() {
+        APIError::Timeout(error.to_string())
+    } else {
+        APIError::Unhandled(error.to_string())
+    }
+}
+
+/// Parses a RFC 3339 timestamp of an API response.
+fn parse_time(time: &str) -> Result<DateTime<Utc>, APIError> {
+    DateTime::parse_from_rfc3339(time)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|_| APIError::Deserialze(format!("invalid timestamp {time}")))
+}
+
+/// Serves the given responses as JSON to consecutive requests on localhost.
+/// Returns the base URL of the server and a handle yielding the raw requests.
+#[cfg(test)]
+pub(crate) async fn mock_server(
+    responses: Vec<(u16, &'static str)>,
+) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let handle = tokio::spawn(async move {
+        let mut requests = vec![];
+        for (status, body) in responses {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = vec![];
+            let mut buffer = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buffer).await.unwrap();
+                if read == 0 {
+                    break;
                 }
+                request.extend_from_slice(&buffer[..read]);
             }
-            status => APIError::Unhandled(status.to_string()),
+            requests.push(String::from_utf8_lossy(&request).into_owned());
+            let response = format!(
+                "HTTP/1.1 {status} Mock\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+                body.len()
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
         }
+        requests
+    });
+    (base_url, handle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use uom::si::mass_per_energy::gram_per_kilowatt_hour;
+
+    struct MockProvider {
+        name: &'static str,
+        kind: IntensityKind,
+        result: Result<f64, APIError>,
+    }
+
+    #[async_trait]
+    impl IntensityProvider for MockProvider {
+        fn name(&self) -> &'static str {
+            self.name
+        }
+
+        fn kind(&self) -> IntensityKind {
+            self.kind
+        }
+
+        async fn carbon_intensity(&self) -> Result<Observation, APIError> {
+            self.result.clone().map(|intensity| Observation {
+                intensity: MassPerEnergy::new::<gram_per_kilowatt_hour>(intensity),
+                observed_at: Utc::now(),
+                region: "mock".to_owned(),
+                valid_until: None,
+                percentile: None,
+                index: None,
+                generation_mix: vec![],
+            })
+        }
+
+        async fn carbon_intensity_forecast(&self) -> Result<Forecast, APIError> {
+            let now = Utc::now();
+            self.result.clone().map(|intensity| Forecast {
+                points: to_forecast_points(
+                    vec![(now, MassPerEnergy::new::<gram_per_kilowatt_hour>(intensity))],
+                    Duration::hours(1),
+                ),
+                generated_at: now,
+                region: "mock".to_owned(),
+            })
+        }
+    }
+
+    fn mock(name: &'static str, result: Result<f64, APIError>) -> Box<dyn IntensityProvider> {
+        mock_of_kind(name, IntensityKind::Marginal, result)
+    }
+
+    fn mock_of_kind(
+        name: &'static str,
+        kind: IntensityKind,
+        result: Result<f64, APIError>,
+    ) -> Box<dyn IntensityProvider> {
+        Box::new(MockProvider { name, kind, result })
+    }
+
+    #[tokio::test]
+    async fn test_request_uses_first_provider() {
+        let providers = vec![mock("first", Ok(100.0)), mock("second", Ok(200.0))];
+
+        let (_, provided) = request_carbon_intensities(&providers, &[]).await.remove(0);
+        let provided = provided.unwrap();
+
+        assert_eq!(provided.provider, "first");
+        assert_eq!(
+            provided.value.intensity.get::<gram_per_kilowatt_hour>(),
+            100.0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_falls_back() {
+        let providers = vec![
+            mock("first", Err(APIError::Unhandled("500".to_owned()))),
+            mock("second", Err(APIError::InvalidCredentials)),
+            mock("third", Ok(300.0)),
+        ];
+
+        let (_, provided) = request_carbon_intensities(&providers, &[]).await.remove(0);
+        let provided = provided.unwrap();
+
+        assert_eq!(provided.provider, "third");
+    }
+
+    #[tokio::test]
+    async fn test_request_forecast_falls_back() {
+        let providers = vec![
+            mock("first", Err(APIError::Timeout("read".to_owned()))),
+            mock("second", Ok(200.0)),
+        ];
+
+        let provided = request_carbon_intensity_forecast(&providers).await.unwrap();
+
+        assert_eq!(provided.provider, "second");
+        assert_eq!(provided.value.points.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_request_forecast_does_not_fall_back_to_other_kind() {
+        let providers = vec![
+            mock_of_kind(
+                "marginal",
+                IntensityKind::Marginal,
+                Err(APIError::Timeout("read".to_owned())),
+            ),
+            mock_of_kind("average", IntensityKind::Average, Ok(300.0)),
+        ];
+
+        let error = request_carbon_intensity_forecast(&providers)
+            .await
+            .unwrap_err();
+
+        assert_eq!(error, APIError::Timeout("read".to_owned()));
+    }
+
+    #[tokio::test]
+    async fn test_request_stops_on_non_fallback_error() {
+        let providers = vec![
+            mock("first", Err(APIError::InvalidRegion("XX".to_owned()))),
+            mock("second", Ok(200.0)),
+        ];
+
+        let (_, provided) = request_carbon_intensities(&providers, &[]).await.remove(0);
+        let error = provided.unwrap_err();
+
+        assert_eq!(error, APIError::InvalidRegion("XX".to_owned()));
+    }
+
+    #[tokio::test]
+    async fn test_request_returns_last_error() {
+        let providers = vec![
+            mock("first", Err(APIError::InvalidCredentials)),
+            mock("second", Err(APIError::Timeout("read".to_owned()))),
+        ];
+
+        let (_, provided) = request_carbon_intensities(&providers, &[]).await.remove(0);
+        let error = provided.unwrap_err();
+
+        assert_eq!(error, APIError::Timeout("read".to_owned()));
+    }
+
+    #[tokio::test]
+    async fn test_request_every_kind() {
+        let providers = vec![
+            mock_of_kind("average", IntensityKind::Average, Ok(300.0)),
+            mock_of_kind(
+                "marginal",
+                IntensityKind::Marginal,
+                Err(APIError::Timeout("read".to_owned())),
+            ),
+            mock_of_kind("fallback", IntensityKind::Marginal, Ok(500.0)),
+        ];
+
+        let results = request_carbon_intensities(&providers, &[]).await;
+
+        let kinds: Vec<(IntensityKind, &str)> = results
+            .iter()
+            .map(|(kind, result)| (*kind, result.as_ref().unwrap().provider))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (IntensityKind::Average, "average"),
+                (IntensityKind::Marginal, "fallback")
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_request_kind_does_not_fall_back_to_other_kind() {
+        let providers = vec![
+            mock_of_kind(
+                "marginal",
+                IntensityKind::Marginal,
+                Err(APIError::Timeout("read".to_owned())),
+            ),
+            mock_of_kind("average", IntensityKind::Average, Ok(300.0)),
+        ];
+
+        let results = request_carbon_intensities(&providers, &[]).await;
+
+        assert_eq!(results[0].0, IntensityKind::Marginal);
+        assert!(results[0].1.is_err());
+        assert_eq!(results[1].1.as_ref().unwrap().provider, "average");
+    }
+
+    #[tokio::test]
+    async fn test_request_skips_kinds() {
+        let providers = vec![
+            mock_of_kind("marginal", IntensityKind::Marginal, Ok(500.0)),
+            mock_of_kind("average", IntensityKind::Average, Ok(300.0)),
+        ];
+
+        let results = request_carbon_intensities(&providers, &[IntensityKind::Average]).await;
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].0, IntensityKind::Marginal);
+    }
+
+    #[test]
+    fn test_to_forecast_points() {
+        let start = Utc.with_ymd_and_hms(2023, 5, 1, 12, 0, 0).unwrap();
+        let intensity = MassPerEnergy::new::<gram_per_kilowatt_hour>(100.0);
+        let starts = vec![
+            (start, intensity),
+            (start + Duration::minutes(5), intensity),
+            (start + Duration::minutes(10), intensity),
+        ];
+
+        let points = to_forecast_points(starts, Duration::hours(1));
+
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0].end, start + Duration::minutes(5));
+        assert_eq!(points[2].start, start + Duration::minutes(10));
+        assert_eq!(points[2].end, start + Duration::minutes(15));
+    }
+
+    #[test]
+    fn test_to_forecast_points_single() {
+        let start = Utc.with_ymd_and_hms(2023, 5, 1, 12, 0, 0).unwrap();
+        let intensity = MassPerEnergy::new::<gram_per_kilowatt_hour>(100.0);
+
+        let points = to_forecast_points(vec![(start, intensity)], Duration::hours(1));
+
+        assert_eq!(points[0].end, start + Duration::hours(1));
     }
 }
@@ -0,0 +1,318 @@
+use crate::{data, errors::APIError};
+use async_trait::async_trait;
+use chrono::Duration;
+use log::debug;
+use reqwest::{Client, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use std::fmt::Debug;
+use uom::si::{f64::MassPerEnergy, mass_per_energy::gram_per_kilowatt_hour};
+
+/// Base URL of the public Electricity Maps API.
+pub(crate) const BASE_URL: &str = "https://api.electricitymap.org/v3";
+const AUTH_HEADER: &str = "auth-token";
+
+pub(crate) struct Api {
+    client: Client,
+    base_url: String,
+    token: String,
+    zone: String,
+}
+
+impl Api {
+    /// Creates a new instance of the Electricity Maps API for the given zone.
+    /// The zone is validated with the config.
+    pub fn new(client: Client, base_url: &str, token: &str, zone: &str) -> Api {
+        Self {
+            client,
+            base_url: String::from(base_url),
+            token: String::from(token),
+            zone: String::from(zone),
+        }
+    }
+
+    /// Requests the latest carbon intensity of the zone from Electricity Maps.
+    pub async fn get_carbon_intensity(&self) -> Result<super::Observation, APIError> {
+        debug!(
+            "Requesting carbon intensity for zone {} from electricity maps.",
+            self.zone
+        );
+        let response: data::api::ElectricityMapResponse =
+            self.request("carbon-intensity/latest").await?;
+        to_observation(response)
+    }
+
+    /// Requests the carbon intensity forecast of the zone from Electricity Maps.
+    pub async fn get_carbon_intensity_forecast(&self) -> Result<super::Forecast, APIError> {
+        debug!(
+            "Requesting carbon intensity forecast for zone {} from electricity maps.",
+            self.zone
+        );
+        let response: data::api::ElectricityMapForecastResponse =
+            self.request("carbon-intensity/forecast").await?;
+        to_forecast(response)
+    }
+
+    async fn request<T: DeserializeOwned + Debug>(&self, endpoint: &str) -> Result<T, APIError> {
+        let url = format!("{}/{endpoint}?zone={}", self.base_url, self.zone);
+        let response = self
+            .client
+            .get(url)
+            .header(AUTH_HEADER, &self.token)
+            .send()
+            .await;
+        // handle request errors
+        let response = match response {
+            Ok(res) => {
+                if let StatusCode::OK = res.status() {
+                    res
+                } else {
+                    return Err(self.handle_error(res).await);
+                }
+            }
+            Err(e) => {
+                return Err(super::request_error(e));
+            }
+        };
+        // handle body parsing errors
+        let response: T = response
+            .json()
+            .await
+            .map_err(|op| APIError::Deserialze(op.to_string()))?;
+        debug!("Got {:#?}.", response);
+        Ok(response)
+    }
+
+    async fn handle_error(&self, response: Response) -> APIError {
+        let status = response.status();
+        let message = response
+            .json::<data::api::ElectricityMapError>()
+            .await
+            .map(|err| err.message)
+            .unwrap_or_default();
+        map_error(status, &message, &self.zone)
+    }
+}
+
+#[async_trait]
+impl super::IntensityProvider for Api {
+    fn name(&self) -> &'static str {
+        "electricity_map"
+    }
+
+    /// Electricity Maps reports the average intensity of the consumed electricity.
+    fn kind(&self) -> super::IntensityKind {
+        super::IntensityKind::Average
+    }
+
+    async fn carbon_intensity(&self) -> Result<super::Observation, APIError> {
+        self.get_carbon_intensity().await
+    }
+
+    async fn carbon_intensity_forecast(&self) -> Result<super::Forecast, APIError> {
+        self.get_carbon_intensity_forecast().await
+    }
+}
+
+/// Converts an Electricity Maps response into an observation.
+/// Electricity Maps publishes values per hour, so a value is valid for one hour.
+fn to_observation(
+    response: data::api::ElectricityMapResponse,
+) -> Result<super::Observation, APIError> {
+    // Electricity Maps returns the carbon intensity in gCO2eq/kWh
+    let intensity = response
+        .carbon_intensity
+        .ok_or(APIError::Unhandled(format!(
+            "Electricity Maps has no carbon intensity for zone {}",
+            response.zone
+        )))?;
+    let observed_at = super::parse_time(&response.datetime)?;
+    Ok(super::Observation {
+        intensity: MassPerEnergy::new::<gram_per_kilowatt_hour>(intensity),
+        observed_at,
+        region: response.zone,
+        valid_until: Some(observed_at + Duration::hours(1)),
+        percentile: None,
+        index: None,
+        generation_mix: vec![],
+    })
+}
+
+/// Converts an Electricity Maps forecast response into a forecast.
+/// Electricity Maps forecasts carbon intensities in gCO2eq/kWh for intervals of one hour.
+fn to_forecast(
+    response: data::api::ElectricityMapForecastResponse,
+) -> Result<super::Forecast, APIError> {
+    let starts = response
+        .forecast
+        .iter()
+        .map(|point| {
+            Ok((
+                super::parse_time(&point.datetime)?,
+                MassPerEnergy::new::<gram_per_kilowatt_hour>(point.carbon_intensity),
+            ))
+        })
+        .collect::<Result<Vec<_>, APIError>>()?;
+    Ok(super::Forecast {
+        points: super::to_forecast_points(starts, Duration::hours(1)),
+        generated_at: super::parse_time(&response.updated_at)?,
+        region: response.zone,
+    })
+}
+
+/// Maps an unsuccessful Electricity Maps response to an [`APIError`].
+fn map_error(status: StatusCode, message: &str, zone: &str) -> APIError {
+    match status {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => APIError::InvalidCredentials,
+        StatusCode::BAD_REQUEST | StatusCode::NOT_FOUND
+            if message.to_lowercase().contains("zone") =>
+        {
+            APIError::InvalidRegion(zone.to_owned())
+        }
+        _ if !message.is_empty() => APIError::Unhandled(message.to_owned()),
+        status => APIError::Unhandled(status.to_string()),
+    }
+}
+
+/// Checks if a zone has the format of an Electricity Maps zone identifier,
+/// e.g. `DE`, `DK-DK1` or `US-CAL-CISO`.
+pub(crate) fn is_valid_zone(zone: &str) -> bool {
+    let mut parts = zone.split('-');
+    let country_valid = parts
+        .next()
+        .map(|country| country.len() == 2 && country.chars().all(|c| c.is_ascii_uppercase()))
+        .unwrap_or(false);
+    country_valid
+        && parts.all(|part| {
+            !part.is_empty()
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn test_is_valid_zone() {
+        assert!(is_valid_zone("DE"));
+        assert!(is_valid_zone("DK-DK1"));
+        assert!(is_valid_zone("US-CAL-CISO"));
+        assert!(!is_valid_zone(""));
+        assert!(!is_valid_zone("de"));
+        assert!(!is_valid_zone("Germany"));
+        assert!(!is_valid_zone("DE-"));
+        assert!(!is_valid_zone("DE?zone=FR"));
+    }
+
+    #[tokio::test]
+    async fn test_request_uses_base_url() {
+        let (base_url, server) = super::super::mock_server(vec![(
+            200,
+            r#"{"zone": "DE", "carbonIntensity": 302, "datetime": "2019-05-21T00:00:00.000Z", "updatedAt": "2019-05-21T00:53:12.823Z"}"#,
+        )])
+        .await;
+        let client = Client::builder()
+            .user_agent("carbond/test")
+            .build()
+            .unwrap();
+        let api = Api::new(client, &base_url, "token", "DE");
+
+        let observation = api.get_carbon_intensity().await.unwrap();
+
+        assert_eq!(observation.intensity.get::<gram_per_kilowatt_hour>(), 302.0);
+        let request = server.await.unwrap().remove(0).to_lowercase();
+        assert!(request.starts_with("get /carbon-intensity/latest?zone=de "));
+        assert!(request.contains("auth-token: token\r\n"));
+        assert!(request.contains("user-agent: carbond/test\r\n"));
+    }
+
+    #[tokio::test]
+    async fn test_request_times_out() {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        let client = Client::builder()
+            .timeout(std::time::Duration::from_millis(100))
+            .build()
+            .unwrap();
+        let api = Api::new(client, &base_url, "token", "DE");
+
+        let error = api.get_carbon_intensity().await.unwrap_err();
+
+        assert!(matches!(error, APIError::Timeout(_)));
+        drop(listener);
+    }
+
+    #[test]
+    fn test_to_observation() {
+        let response: data::api::ElectricityMapResponse = serde_json::from_str(
+            r#"{"zone": "DE", "carbonIntensity": 302, "datetime": "2019-05-21T00:00:00.000Z", "updatedAt": "2019-05-21T00:53:12.823Z", "createdAt": "2019-05-18T00:58:12.520Z", "emissionFactorType": "lifecycle", "isEstimated": true, "estimationMethod": "TIME_SLICER_AVERAGE"}"#,
+        )
+        .unwrap();
+
+        let observation = to_observation(response).unwrap();
+
+        assert_eq!(observation.intensity.get::<gram_per_kilowatt_hour>(), 302.0);
+        assert_eq!(observation.region, "DE");
+        assert_eq!(
+            observation.valid_until,
+            Some(Utc.with_ymd_and_hms(2019, 5, 21, 1, 0, 0).unwrap())
+        );
+    }
+
+    #[test]
+    fn test_to_observation_without_intensity() {
+        let response: data::api::ElectricityMapResponse = serde_json::from_str(
+            r#"{"zone": "DE", "carbonIntensity": null, "datetime": "2019-05-21T00:00:00.000Z", "updatedAt": "2019-05-21T00:53:12.823Z"}"#,
+        )
+        .unwrap();
+
+        assert!(to_observation(response).is_err());
+    }
+
+    #[test]
+    fn test_to_forecast() {
+        let response: data::api::ElectricityMapForecastResponse = serde_json::from_str(
+            r#"{"zone": "DE", "forecast": [
+                {"carbonIntensity": 326, "datetime": "2020-02-22T08:00:00.000Z"},
+                {"carbonIntensity": 318, "datetime": "2020-02-22T09:00:00.000Z"}
+            ], "updatedAt": "2020-02-22T08:27:24.467Z"}"#,
+        )
+        .unwrap();
+
+        let forecast = to_forecast(response).unwrap();
+
+        assert_eq!(forecast.region, "DE");
+        assert_eq!(forecast.points.len(), 2);
+        assert_eq!(
+            forecast.points[1].end,
+            Utc.with_ymd_and_hms(2020, 2, 22, 10, 0, 0).unwrap()
+        );
+        assert_eq!(
+            forecast.points[1].intensity.get::<gram_per_kilowatt_hour>(),
+            318.0
+        );
+    }
+
+    #[test]
+    fn test_map_error() {
+        assert_eq!(
+            map_error(StatusCode::UNAUTHORIZED, "", "DE"),
+            APIError::InvalidCredentials
+        );
+        assert_eq!(
+            map_error(StatusCode::NOT_FOUND, "Zone 'XX' does not exist.", "XX"),
+            APIError::InvalidRegion("XX".to_owned())
+        );
+        assert_eq!(
+            map_error(StatusCode::TOO_MANY_REQUESTS, "Rate limit exceeded", "DE"),
+            APIError::Unhandled("Rate limit exceeded".to_owned())
+        );
+        assert_eq!(
+            map_error(StatusCode::BAD_GATEWAY, "", "DE"),
+            APIError::Unhandled(StatusCode::BAD_GATEWAY.to_string())
+        );
+    }
+}
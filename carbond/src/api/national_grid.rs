@@ -0,0 +1,383 @@
+use crate::{
+    data::{self, config::internal::NationalGridRegion},
+    errors::APIError,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, NaiveDateTime, TimeZone, Utc};
+use log::debug;
+use reqwest::{Client, Response, StatusCode};
+use uom::si::{f64::MassPerEnergy, mass_per_energy::gram_per_kilowatt_hour};
+
+/// Base URL of the public National Grid Carbon Intensity API.
+pub(crate) const BASE_URL: &str = "https://api.carbonintensity.org.uk";
+
+/// Timestamps of the API are given in minutes, e.g. `2023-05-01T12:00Z`.
+const TIME_FORMAT: &str = "%Y-%m-%dT%H:%MZ";
+
+pub(crate) struct Api {
+    client: Client,
+    base_url: String,
+    region: NationalGridRegion,
+}
+
+impl Api {
+    /// Creates a new instance of the National Grid API for an outward code or region id.
+    /// The region is validated with the config.
+    pub fn new(client: Client, base_url: &str, region: &NationalGridRegion) -> Api {
+        Self {
+            client,
+            base_url: String::from(base_url),
+            region: region.clone(),
+        }
+    }
+
+    /// Requests the carbon intensity of the current half hour.
+    pub async fn get_carbon_intensity(&self) -> Result<super::Observation, APIError> {
+        debug!(
+            "Requesting carbon intensity for {} from national grid.",
+            self.region_path()
+        );
+        let response = self
+            .request(&format!("regional/{}", self.region_path()))
+            .await?;
+        to_observation(response)
+    }
+
+    /// Requests the forecast of the next 24 hours in half hour intervals.
+    pub async fn get_carbon_intensity_forecast(&self) -> Result<super::Forecast, APIError> {
+        debug!(
+            "Requesting carbon intensity forecast for {} from national grid.",
+            self.region_path()
+        );
+        let from = Utc::now().format(TIME_FORMAT);
+        let response = self
+            .request(&format!(
+                "regional/intensity/{from}/fw24h/{}",
+                self.region_path()
+            ))
+            .await?;
+        to_forecast(response)
+    }
+
+    fn region_path(&self) -> String {
+        match &self.region {
+            NationalGridRegion::Postcode(postcode) => format!("postcode/{postcode}"),
+            NationalGridRegion::RegionId(id) => format!("regionid/{id}"),
+        }
+    }
+
+    async fn request(&self, endpoint: &str) -> Result<data::api::NationalGridResponse, APIError> {
+        let url = format!("{}/{endpoint}", self.base_url);
+        let response = self
+            .client
+            .get(url)
+            .header(reqwest::header::ACCEPT, "application/json")
+            .send()
+            .await;
+        // handle request errors
+        let response = match response {
+            Ok(res) => {
+                if let StatusCode::OK = res.status() {
+                    res
+                } else {
+                    return Err(self.handle_error(res).await);
+                }
+            }
+            Err(e) => {
+                return Err(super::request_error(e));
+            }
+        };
+        // handle body parsing errors
+        let response: data::api::NationalGridResponse = response
+            .json()
+            .await
+            .map_err(|op| APIError::Deserialze(op.to_string()))?;
+        debug!("Got {:#?}.", response);
+        Ok(response)
+    }
+
+    async fn handle_error(&self, response: Response) -> APIError {
+        let status = response.status();
+        let message = response
+            .json::<data::api::NationalGridError>()
+            .await
+            .map(|err| err.error.message)
+            .unwrap_or_default();
+        let region = match &self.region {
+            NationalGridRegion::Postcode(postcode) => postcode.clone(),
+            NationalGridRegion::RegionId(id) => id.to_string(),
+        };
+        map_error(status, &message, &region)
+    }
+}
+
+#[async_trait]
+impl super::IntensityProvider for Api {
+    fn name(&self) -> &'static str {
+        "national_grid"
+    }
+
+    /// The API reports the average intensity of the generation in a region.
+    fn kind(&self) -> super::IntensityKind {
+        super::IntensityKind::Average
+    }
+
+    async fn carbon_intensity(&self) -> Result<super::Observation, APIError> {
+        self.get_carbon_intensity().await
+    }
+
+    async fn carbon_intensity_forecast(&self) -> Result<super::Forecast, APIError> {
+        self.get_carbon_intensity_forecast().await
+    }
+}
+
+/// Returns the regions of a response, the current intensity is given for a list of regions.
+fn regions(response: data::api::NationalGridResponse) -> Vec<data::api::NationalGridRegion> {
+    match response.data {
+        data::api::NationalGridRegions::Many(regions) => regions,
+        data::api::NationalGridRegions::One(region) => vec![region],
+    }
+}
+
+/// Converts the current half hour of a National Grid response into an observation.
+/// Regional values are forecasts in gCO2/kWh, actual values are only published nationally.
+fn to_observation(
+    response: data::api::NationalGridResponse,
+) -> Result<super::Observation, APIError> {
+    let region = regions(response)
+        .into_iter()
+        .next()
+        .ok_or_else(|| APIError::Unhandled("National Grid returned no region".to_owned()))?;
+    let period = region.data.first().ok_or_else(|| {
+        APIError::Unhandled(format!(
+            "National Grid has no carbon intensity for {}",
+            region.shortname
+        ))
+    })?;
+    Ok(super::Observation {
+        intensity: to_intensity(period)?,
+        observed_at: parse_time(&period.from)?,
+        region: region.shortname.clone(),
+        valid_until: Some(parse_time(&period.to)?),
+        percentile: None,
+        index: to_index(&period.intensity.index),
+        generation_mix: period
+            .generationmix
+            .iter()
+            .map(|fuel| (fuel.fuel.clone(), fuel.perc))
+            .collect(),
+    })
+}
+
+/// Converts a National Grid forecast response into a forecast.
+/// The forecast is generated for half hour intervals starting with the current one.
+fn to_forecast(response: data::api::NationalGridResponse) -> Result<super::Forecast, APIError> {
+    let region = regions(response)
+        .into_iter()
+        .next()
+        .ok_or_else(|| APIError::Unhandled("National Grid returned no region".to_owned()))?;
+    let starts = region
+        .data
+        .iter()
+        .map(|period| Ok((parse_time(&period.from)?, to_intensity(period)?)))
+        .collect::<Result<Vec<_>, APIError>>()?;
+    let generated_at = starts
+        .first()
+        .map(|(start, _)| *start)
+        .unwrap_or_else(Utc::now);
+    Ok(super::Forecast {
+        points: super::to_forecast_points(starts, Duration::minutes(30)),
+        generated_at,
+        region: region.shortname,
+    })
+}
+
+fn to_intensity(period: &data::api::NationalGridPeriod) -> Result<MassPerEnergy, APIError> {
+    period
+        .intensity
+        .forecast
+        .map(MassPerEnergy::new::<gram_per_kilowatt_hour>)
+        .ok_or_else(|| {
+            APIError::Unhandled(format!(
+                "National Grid has no carbon intensity from {}",
+                period.from
+            ))
+        })
+}
+
+/// Maps the index band of the API, unknown bands are ignored.
+fn to_index(index: &str) -> Option<super::IntensityIndex> {
+    match index {
+        "very low" => Some(super::IntensityIndex::VeryLow),
+        "low" => Some(super::IntensityIndex::Low),
+        "moderate" => Some(super::IntensityIndex::Moderate),
+        "high" => Some(super::IntensityIndex::High),
+        "very high" => Some(super::IntensityIndex::VeryHigh),
+        _ => None,
+    }
+}
+
+/// Parses a timestamp of the API, which omits the seconds.
+fn parse_time(time: &str) -> Result<DateTime<Utc>, APIError> {
+    match NaiveDateTime::parse_from_str(time, TIME_FORMAT) {
+        Ok(time) => Ok(Utc.from_utc_datetime(&time)),
+        Err(_) => super::parse_time(time),
+    }
+}
+
+/// Maps an unsuccessful National Grid response to an [`APIError`].
+fn map_error(status: StatusCode, message: &str, region: &str) -> APIError {
+    match status {
+        StatusCode::BAD_REQUEST => APIError::InvalidRegion(region.to_owned()),
+        _ if !message.is_empty() => APIError::Unhandled(message.to_owned()),
+        status => APIError::Unhandled(status.to_string()),
+    }
+}
+
+/// Extracts the outward code of a UK postcode, e.g. `RG10` of `rg10 1aa`.
+/// The API only resolves outward codes.
+pub(crate) fn outward_code(postcode: &str) -> Option<String> {
+    // postcodes are ASCII, which also keeps slicing off the inward code on character boundaries
+    if !postcode.is_ascii() {
+        return None;
+    }
+    let postcode = postcode.trim().to_ascii_uppercase();
+    let outward = match postcode.split_once(' ') {
+        Some((outward, _)) => outward.to_owned(),
+        // full postcodes without space end with a three character inward code
+        None if postcode.len() > 4 => postcode[..postcode.len() - 3].to_owned(),
+        None => postcode,
+    };
+    let valid = (2..=4).contains(&outward.len())
+        && outward.starts_with(|c: char| c.is_ascii_alphabetic())
+        && outward.chars().all(|c| c.is_ascii_alphanumeric());
+    valid.then_some(outward)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REGIONAL_POSTCODE: &str =
+        include_str!("../../tests/fixtures/national_grid/regional_postcode.json");
+    const REGIONAL_REGIONID: &str =
+        include_str!("../../tests/fixtures/national_grid/regional_regionid.json");
+    const FORECAST_POSTCODE: &str =
+        include_str!("../../tests/fixtures/national_grid/forecast_postcode.json");
+    const ERROR_POSTCODE: &str =
+        include_str!("../../tests/fixtures/national_grid/error_postcode.json");
+
+    fn fixture(raw: &str) -> data::api::NationalGridResponse {
+        serde_json::from_str(raw).unwrap()
+    }
+
+    #[test]
+    fn test_outward_code() {
+        assert_eq!(outward_code("RG10"), Some("RG10".to_owned()));
+        assert_eq!(outward_code("rg10 1aa"), Some("RG10".to_owned()));
+        assert_eq!(outward_code("SW1A1AA"), Some("SW1A".to_owned()));
+        assert_eq!(outward_code("G1"), Some("G1".to_owned()));
+        assert_eq!(outward_code(""), None);
+        assert_eq!(outward_code("RG10/../.."), None);
+        assert_eq!(outward_code("ÄB12CDE"), None);
+        assert_eq!(outward_code("RG1ÄAA"), None);
+    }
+
+    #[test]
+    fn test_to_observation() {
+        let observation = to_observation(fixture(REGIONAL_POSTCODE)).unwrap();
+
+        assert_eq!(observation.intensity.get::<gram_per_kilowatt_hour>(), 143.0);
+        assert_eq!(observation.region, "South England");
+        assert_eq!(
+            observation.observed_at,
+            Utc.with_ymd_and_hms(2023, 5, 1, 12, 0, 0).unwrap()
+        );
+        assert_eq!(
+            observation.valid_until,
+            Some(Utc.with_ymd_and_hms(2023, 5, 1, 12, 30, 0).unwrap())
+        );
+        assert_eq!(
+            observation.index,
+            Some(super::super::IntensityIndex::Moderate)
+        );
+        assert_eq!(observation.generation_mix.len(), 9);
+        assert_eq!(observation.generation_mix[3], ("gas".to_owned(), 31.5));
+    }
+
+    #[test]
+    fn test_to_observation_by_region_id() {
+        let observation = to_observation(fixture(REGIONAL_REGIONID)).unwrap();
+
+        assert_eq!(observation.region, "Scotland");
+        assert_eq!(
+            observation.index,
+            Some(super::super::IntensityIndex::VeryLow)
+        );
+    }
+
+    #[test]
+    fn test_to_forecast() {
+        let forecast = to_forecast(fixture(FORECAST_POSTCODE)).unwrap();
+
+        assert_eq!(forecast.region, "South England");
+        assert_eq!(forecast.points.len(), 3);
+        assert_eq!(
+            forecast.points[2].end,
+            Utc.with_ymd_and_hms(2023, 5, 1, 13, 30, 0).unwrap()
+        );
+        assert_eq!(
+            forecast.points[1].intensity.get::<gram_per_kilowatt_hour>(),
+            131.0
+        );
+    }
+
+    #[test]
+    fn test_to_index() {
+        assert_eq!(
+            to_index("very low"),
+            Some(super::super::IntensityIndex::VeryLow)
+        );
+        assert_eq!(
+            to_index("very high"),
+            Some(super::super::IntensityIndex::VeryHigh)
+        );
+        assert_eq!(to_index("unknown"), None);
+    }
+
+    #[tokio::test]
+    async fn test_request_paths() {
+        let (base_url, server) =
+            super::super::mock_server(vec![(200, REGIONAL_REGIONID), (200, FORECAST_POSTCODE)])
+                .await;
+
+        let api = Api::new(Client::new(), &base_url, &NationalGridRegion::RegionId(16));
+        api.get_carbon_intensity().await.unwrap();
+        let api = Api::new(
+            Client::new(),
+            &base_url,
+            &NationalGridRegion::Postcode("RG10".to_owned()),
+        );
+        api.get_carbon_intensity_forecast().await.unwrap();
+
+        let requests = server.await.unwrap();
+        assert!(requests[0].starts_with("GET /regional/regionid/16 "));
+        assert!(requests[1].starts_with("GET /regional/intensity/"));
+        assert!(requests[1].contains("/fw24h/postcode/RG10 "));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_postcode() {
+        let (base_url, _server) = super::super::mock_server(vec![(400, ERROR_POSTCODE)]).await;
+        let api = Api::new(
+            Client::new(),
+            &base_url,
+            &NationalGridRegion::Postcode("ZZ99".to_owned()),
+        );
+
+        assert_eq!(
+            api.get_carbon_intensity().await.unwrap_err(),
+            APIError::InvalidRegion("ZZ99".to_owned())
+        );
+    }
+}
@@ -0,0 +1,721 @@
+use crate::{
+    data::{
+        self,
+        config::internal::{SignalType, WattTime},
+    },
+    errors::APIError,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use log::debug;
+use reqwest::{Client, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use std::{fmt::Debug, future::Future, sync::Arc, time::Instant};
+use tokio::sync::Mutex;
+use uom::si::{
+    f64::MassPerEnergy,
+    mass_per_energy::{
+        gram_per_kilowatt_hour, kilogram_per_megawatt_hour, pound_per_megawatt_hour,
+    },
+};
+
+#[derive(Debug)]
+pub struct Unauthorized;
+
+#[derive(Debug)]
+pub struct Authorized {
+    token: String,
+}
+
+/// WattTime tokens expire after 30 minutes, they are renewed early
+/// so they do not expire during a request.
+const TOKEN_LIFETIME: std::time::Duration = std::time::Duration::from_secs(25 * 60);
+
+/// Token of the last login, shared by all requests so WattTime's rate limited login
+/// is only requested when the token expired or was rejected.
+#[derive(Clone, Default)]
+pub struct TokenCache(Arc<Mutex<Option<CachedToken>>>);
+
+struct CachedToken {
+    /// Base URL and username the token was issued for.
+    account: (String, String),
+    token: String,
+    expires_at: Instant,
+}
+
+impl TokenCache {
+    /// Drops the cached token, e.g. after it was rejected.
+    pub async fn clear(&self) {
+        *self.0.lock().await = None;
+    }
+}
+
+impl Debug for TokenCache {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("TokenCache([redacted])")
+    }
+}
+
+/// Base URL of the public WattTime API, data endpoints are below `/v3`.
+pub(crate) const BASE_URL: &str = "https://api.watttime.org";
+
+pub(crate) struct Api<State = Unauthorized> {
+    client: Client,
+    base_url: String,
+    username: String,
+    password: String,
+    region: String,
+    signal_type: SignalType,
+    tokens: TokenCache,
+    state: State,
+}
+
+impl Api<Unauthorized> {
+    /// creates a new unauthorized instance of the API that reuses the cached token if possible
+    pub fn new(client: Client, config: &WattTime, tokens: TokenCache) -> Api {
+        Self {
+            client,
+            base_url: config.base_url.clone(),
+            username: String::from(config.username.expose()),
+            password: String::from(config.password.expose()),
+            region: config.region.clone(),
+            signal_type: config.signal_type,
+            tokens,
+            state: Unauthorized,
+        }
+    }
+
+    /// Returns an authorized instance with the cached token or logs in if it expired.
+    /// Concurrent requests wait for a single login.
+    pub async fn authorized(&self) -> Result<Api<Authorized>, APIError> {
+        let mut cached = self.tokens.0.lock().await;
+        let account = (self.base_url.clone(), self.username.clone());
+        if let Some(token) = cached.as_ref() {
+            if token.account == account && token.expires_at > Instant::now() {
+                return Ok(self.with_token(token.token.clone()));
+            }
+        }
+        let api = self.login().await?;
+        *cached = Some(CachedToken {
+            account,
+            token: api.state.token.clone(),
+            expires_at: Instant::now() + TOKEN_LIFETIME,
+        });
+        Ok(api)
+    }
+
+    /// Runs a request with an authorized instance.
+    /// Logs in again and retries once if the token was rejected.
+    async fn request<T, F, Fut>(&self, request: F) -> Result<T, APIError>
+    where
+        F: Fn(Api<Authorized>) -> Fut,
+        Fut: Future<Output = Result<T, APIError>>,
+    {
+        match request(self.authorized().await?).await {
+            Err(APIError::Authentication) => {
+                debug!("WattTime rejected the token, logging in again.");
+                self.tokens.clear().await;
+                request(self.authorized().await?).await
+            }
+            result => result,
+        }
+    }
+
+    /// Requests the latest value of the signal in the unit WattTime reports it in.
+    pub async fn signal_value(&self) -> Result<super::Provided<super::SignalValue>, APIError> {
+        let value = self
+            .request(|api| async move { api.get_signal_value().await })
+            .await?;
+        Ok(super::Provided {
+            value,
+            provider: "watt_time",
+            fetched_at: Utc::now(),
+        })
+    }
+
+    fn with_token(&self, token: String) -> Api<Authorized> {
+        Api {
+            client: self.client.clone(),
+            base_url: self.base_url.clone(),
+            username: self.username.clone(),
+            password: self.password.clone(),
+            region: self.region.clone(),
+            signal_type: self.signal_type,
+            tokens: self.tokens.clone(),
+            state: Authorized { token },
+        }
+    }
+
+    /// performs a login attempt to obtain a token from the API.
+    pub async fn login(&self) -> Result<Api<Authorized>, APIError> {
+        debug!("Request login to wattime API.");
+        let response = self
+            .client
+            .get(format!("{}/login", self.base_url))
+            .basic_auth(&self.username, Some(&self.password))
+            .send()
+            .await;
+
+        // handle request errors
+        let response = match response {
+            Ok(res) => {
+                if let StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN = res.status() {
+                    return Err(APIError::InvalidCredentials);
+                }
+                res
+            }
+            Err(e) => {
+                return Err(super::request_error(e));
+            }
+        };
+
+        // handle body parsing errors
+        let response: data::api::WattTimeLoginResponse = match response.json().await {
+            Ok(res) => res,
+            Err(e) => return Err(APIError::Deserialze(e.to_string())),
+        };
+        let api_authorized = self.with_token(response.token);
+        debug!("Logged in successfully.");
+        Ok(api_authorized)
+    }
+}
+
+#[async_trait]
+impl super::IntensityProvider for Api<Unauthorized> {
+    fn name(&self) -> &'static str {
+        "watt_time"
+    }
+
+    fn kind(&self) -> super::IntensityKind {
+        match self.signal_type {
+            SignalType::Co2Aoer => super::IntensityKind::Average,
+            SignalType::Co2Moer | SignalType::HealthDamage => super::IntensityKind::Marginal,
+        }
+    }
+
+    async fn carbon_intensity(&self) -> Result<super::Observation, APIError> {
+        self.request(|api| async move { api.get_current().await })
+            .await
+    }
+
+    async fn carbon_intensity_forecast(&self) -> Result<super::Forecast, APIError> {
+        self.request(|api| async move { api.get_forecast().await })
+            .await
+    }
+}
+
+impl Api<Authorized> {
+    /// Requests the latest value of the signal from the historical data, together with
+    /// its signal index if the signal is `co2_moer` and the account has access to it.
+    /// WattTime only offers the signal index for `co2_moer`.
+    pub async fn get_current(&self) -> Result<super::Observation, APIError> {
+        let end = Utc::now();
+        let response = self.get_historical(end - Duration::hours(1), end).await?;
+        let mut observation = to_observation(response)?;
+        if self.signal_type != SignalType::Co2Moer {
+            return Ok(observation);
+        }
+        observation.percentile = match self.get_signal_index().await {
+            Ok(percentile) => Some(percentile),
+            Err(e) => {
+                debug!("No signal index from wattime: {e}");
+                None
+            }
+        };
+        Ok(observation)
+    }
+
+    /// Requests the latest value of the signal from the historical data, keeping its unit.
+    pub async fn get_signal_value(&self) -> Result<super::SignalValue, APIError> {
+        let end = Utc::now();
+        let response = self.get_historical(end - Duration::hours(1), end).await?;
+        to_signal_value(response)
+    }
+
+    /// Requests the signal values between `start` and `end`.
+    pub async fn get_historical(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<data::api::WattTimeSignalResponse, APIError> {
+        debug!(
+            "Requesting {} history for region {} from wattime.",
+            signal_name(self.signal_type),
+            self.region
+        );
+        self.request_watt_time(
+            "historical",
+            &[
+                ("start", start.to_rfc3339_opts(SecondsFormat::Secs, true)),
+                ("end", end.to_rfc3339_opts(SecondsFormat::Secs, true)),
+            ],
+        )
+        .await
+    }
+
+    /// Requests the percentile of the current signal value compared to the next day.
+    pub async fn get_signal_index(&self) -> Result<f64, APIError> {
+        let response: data::api::WattTimeSignalResponse =
+            self.request_watt_time("signal-index", &[]).await?;
+        response
+            .data
+            .last()
+            .map(|point| point.value)
+            .ok_or_else(|| APIError::Deserialze("empty signal index".to_owned()))
+    }
+
+    /// Requests the signal forecast from WattTime.
+    pub async fn get_forecast(&self) -> Result<super::Forecast, APIError> {
+        debug!(
+            "Requesting {} forecast for region {} from wattime.",
+            signal_name(self.signal_type),
+            self.region
+        );
+        let response: data::api::WattTimeSignalResponse =
+            self.request_watt_time("forecast", &[]).await?;
+        to_forecast(response)
+    }
+
+    /// Requests a v3 endpoint for the configured region and signal type.
+    async fn request_watt_time<T: DeserializeOwned + Debug>(
+        &self,
+        endpoint: &str,
+        query: &[(&str, String)],
+    ) -> Result<T, APIError> {
+        let response = self
+            .client
+            .get(format!("{}/v3/{endpoint}", self.base_url))
+            .query(&[
+                ("region", self.region.as_str()),
+                ("signal_type", signal_name(self.signal_type)),
+            ])
+            .query(query)
+            .bearer_auth(&self.state.token)
+            .send()
+            .await;
+        // handle request errors
+        let response = match response {
+            Ok(res) => {
+                if let StatusCode::OK = res.status() {
+                    res
+                } else {
+                    return Err(self.handle_error(res).await);
+                }
+            }
+            Err(e) => {
+                return Err(super::request_error(e));
+            }
+        };
+        // handle body parsing errors
+        let response: T = response
+            .json()
+            .await
+            .map_err(|op| APIError::Deserialze(op.to_string()))?;
+        debug!("Got {:#?}.", response);
+        Ok(response)
+    }
+
+    async fn handle_error(&self, response: Response) -> APIError {
+        let status = response.status();
+        let message = response
+            .json::<data::api::WattTimeError>()
+            .await
+            .map(|err| err.message)
+            .unwrap_or_default();
+        map_error(status, &message, &self.region)
+    }
+}
+
+/// Name of a signal type in WattTime requests.
+fn signal_name(signal_type: SignalType) -> &'static str {
+    match signal_type {
+        SignalType::Co2Moer => "co2_moer",
+        SignalType::Co2Aoer => "co2_aoer",
+        SignalType::HealthDamage => "health_damage",
+    }
+}
+
+/// Maps an unsuccessful WattTime response to an [`APIError`].
+fn map_error(status: StatusCode, message: &str, region: &str) -> APIError {
+    match status {
+        StatusCode::UNAUTHORIZED => APIError::Authentication,
+        StatusCode::BAD_REQUEST if message.to_lowercase().contains("region") => {
+            APIError::InvalidRegion(region.to_owned())
+        }
+        _ if !message.is_empty() => APIError::Unhandled(message.to_owned()),
+        status => APIError::Unhandled(status.to_string()),
+    }
+}
+
+/// Converts a value in the units of the response metadata into a carbon intensity.
+/// Signals that are no mass per energy, like the health damage in $/MWh, are rejected.
+fn to_intensity(value: f64, units: &str) -> Result<MassPerEnergy, APIError> {
+    match units {
+        "lbs_co2_per_mwh" => Ok(MassPerEnergy::new::<pound_per_megawatt_hour>(value)),
+        "kg_co2_per_mwh" => Ok(MassPerEnergy::new::<kilogram_per_megawatt_hour>(value)),
+        "g_co2_per_kwh" => Ok(MassPerEnergy::new::<gram_per_kilowatt_hour>(value)),
+        units => Err(APIError::Deserialze(format!(
+            "unit {units} is not a carbon intensity"
+        ))),
+    }
+}
+
+/// Period of the data points of a response, WattTime uses 5 minutes by default.
+fn period(meta: &data::api::WattTimeMeta) -> Duration {
+    meta.data_point_period_seconds
+        .map(Duration::seconds)
+        .unwrap_or(Duration::minutes(5))
+}
+
+/// Converts the latest data point of a WattTime response into an observation.
+/// The value is valid for the data point period given by WattTime.
+fn to_observation(
+    response: data::api::WattTimeSignalResponse,
+) -> Result<super::Observation, APIError> {
+    let signal = to_signal_value(response)?;
+    Ok(super::Observation {
+        intensity: to_intensity(signal.value, &signal.unit)?,
+        observed_at: signal.observed_at,
+        region: signal.region,
+        valid_until: signal.valid_until,
+        percentile: None,
+        index: None,
+        generation_mix: vec![],
+    })
+}
+
+/// Converts the latest data point of a WattTime response into a value in the units of the
+/// response metadata. The value is valid for the data point period given by WattTime.
+fn to_signal_value(
+    response: data::api::WattTimeSignalResponse,
+) -> Result<super::SignalValue, APIError> {
+    let point = response.data.last().ok_or_else(|| {
+        APIError::Unhandled(format!(
+            "WattTime has no recent data for region {}",
+            response.meta.region
+        ))
+    })?;
+    let observed_at = super::parse_time(&point.point_time)?;
+    Ok(super::SignalValue {
+        value: point.value,
+        unit: response.meta.units.clone(),
+        observed_at,
+        region: response.meta.region.clone(),
+        valid_until: Some(observed_at + period(&response.meta)),
+    })
+}
+
+/// Converts a WattTime forecast response into a forecast.
+fn to_forecast(response: data::api::WattTimeSignalResponse) -> Result<super::Forecast, APIError> {
+    let starts = response
+        .data
+        .iter()
+        .map(|point| {
+            Ok((
+                super::parse_time(&point.point_time)?,
+                to_intensity(point.value, &response.meta.units)?,
+            ))
+        })
+        .collect::<Result<Vec<_>, APIError>>()?;
+    let generated_at = match &response.meta.generated_at {
+        Some(generated_at) => super::parse_time(generated_at)?,
+        None => starts
+            .first()
+            .map(|(start, _)| *start)
+            .unwrap_or_else(Utc::now),
+    };
+    Ok(super::Forecast {
+        points: super::to_forecast_points(starts, period(&response.meta)),
+        generated_at,
+        region: response.meta.region,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{api::IntensityProvider, data::config::internal::Secret};
+    use chrono::TimeZone;
+
+    const LOGIN_RESPONSE: &str = r#"{"token": "first"}"#;
+    const FORECAST_RESPONSE: &str = r#"{
+        "data": [
+            {"point_time": "2023-07-18T22:00:00+00:00", "value": 870.0},
+            {"point_time": "2023-07-18T22:05:00+00:00", "value": 865.5}
+        ],
+        "meta": {
+            "data_point_period_seconds": 300,
+            "region": "CAISO_NORTH",
+            "warnings": [],
+            "signal_type": "co2_moer",
+            "model": {"date": "2023-03-01", "type": "binned_regression"},
+            "units": "lbs_co2_per_mwh",
+            "generated_at_period_seconds": 300,
+            "generated_at": "2023-07-18T21:55:00+00:00"
+        }
+    }"#;
+    const HISTORICAL_RESPONSE: &str = r#"{
+        "data": [
+            {"point_time": "2023-07-18T21:50:00+00:00", "value": 412.0},
+            {"point_time": "2023-07-18T21:55:00+00:00", "value": 401.0}
+        ],
+        "meta": {
+            "data_point_period_seconds": 300,
+            "region": "CAISO_NORTH",
+            "warnings": [],
+            "signal_type": "co2_aoer",
+            "units": "lbs_co2_per_mwh"
+        }
+    }"#;
+    const SIGNAL_INDEX_RESPONSE: &str = r#"{
+        "data": [{"point_time": "2023-07-18T21:55:00+00:00", "value": 85.0}],
+        "meta": {
+            "data_point_period_seconds": 300,
+            "region": "CAISO_NORTH",
+            "warnings": [],
+            "signal_type": "co2_moer",
+            "units": "percentile"
+        }
+    }"#;
+    const HEALTH_DAMAGE_RESPONSE: &str = r#"{
+        "data": [{"point_time": "2023-07-18T21:55:00+00:00", "value": 12.5}],
+        "meta": {
+            "data_point_period_seconds": 300,
+            "region": "CAISO_NORTH",
+            "warnings": [],
+            "signal_type": "health_damage",
+            "units": "$_per_mwh"
+        }
+    }"#;
+    const FORECAST_PATH: &str = "/v3/forecast?region=CAISO_NORTH&signal_type=co2_moer";
+
+    fn config(base_url: &str, username: &str) -> WattTime {
+        WattTime {
+            region: "CAISO_NORTH".to_owned(),
+            base_url: base_url.to_owned(),
+            signal_type: SignalType::Co2Moer,
+            username: Secret::new(username.to_owned()),
+            password: Secret::new("dce".to_owned()),
+        }
+    }
+
+    fn api(base_url: &str, tokens: TokenCache) -> Api {
+        Api::new(Client::new(), &config(base_url, "abc"), tokens)
+    }
+
+    fn paths(requests: &[String]) -> Vec<&str> {
+        requests
+            .iter()
+            .map(|request| request.split(' ').nth(1).unwrap_or_default())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_token_is_reused() {
+        let (base_url, server) = super::super::mock_server(vec![
+            (200, LOGIN_RESPONSE),
+            (200, FORECAST_RESPONSE),
+            (200, FORECAST_RESPONSE),
+        ])
+        .await;
+        let tokens = TokenCache::default();
+
+        api(&base_url, tokens.clone())
+            .carbon_intensity_forecast()
+            .await
+            .unwrap();
+        api(&base_url, tokens)
+            .carbon_intensity_forecast()
+            .await
+            .unwrap();
+
+        let requests = server.await.unwrap();
+        assert_eq!(
+            paths(&requests),
+            vec!["/login", FORECAST_PATH, FORECAST_PATH]
+        );
+        assert!(requests[2].contains("Bearer first"));
+    }
+
+    #[tokio::test]
+    async fn test_login_again_after_unauthorized() {
+        let (base_url, server) = super::super::mock_server(vec![
+            (200, LOGIN_RESPONSE),
+            (401, "{}"),
+            (200, r#"{"token": "second"}"#),
+            (200, FORECAST_RESPONSE),
+        ])
+        .await;
+
+        let forecast = api(&base_url, TokenCache::default())
+            .carbon_intensity_forecast()
+            .await
+            .unwrap();
+
+        assert_eq!(forecast.region, "CAISO_NORTH");
+        let requests = server.await.unwrap();
+        assert_eq!(
+            paths(&requests),
+            vec!["/login", FORECAST_PATH, "/login", FORECAST_PATH]
+        );
+        assert!(requests[3].contains("Bearer second"));
+    }
+
+    #[tokio::test]
+    async fn test_token_of_other_account_is_not_reused() {
+        let (base_url, server) =
+            super::super::mock_server(vec![(200, LOGIN_RESPONSE), (200, LOGIN_RESPONSE)]).await;
+        let tokens = TokenCache::default();
+
+        api(&base_url, tokens.clone()).authorized().await.unwrap();
+        Api::new(Client::new(), &config(&base_url, "xyz"), tokens)
+            .authorized()
+            .await
+            .unwrap();
+
+        assert_eq!(paths(&server.await.unwrap()), vec!["/login", "/login"]);
+    }
+
+    #[tokio::test]
+    async fn test_current_with_signal_index() {
+        let (base_url, server) = super::super::mock_server(vec![
+            (200, LOGIN_RESPONSE),
+            (200, HISTORICAL_RESPONSE),
+            (200, SIGNAL_INDEX_RESPONSE),
+        ])
+        .await;
+
+        let observation = api(&base_url, TokenCache::default())
+            .carbon_intensity()
+            .await
+            .unwrap();
+
+        assert_eq!(observation.percentile, Some(85.0));
+        let requests = server.await.unwrap();
+        let paths = paths(&requests);
+        assert!(
+            paths[1].starts_with("/v3/historical?region=CAISO_NORTH&signal_type=co2_moer&start=")
+        );
+        assert_eq!(
+            paths[2],
+            "/v3/signal-index?region=CAISO_NORTH&signal_type=co2_moer"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_current_without_signal_index_for_aoer() {
+        let (base_url, server) = super::super::mock_server(vec![
+            (200, LOGIN_RESPONSE),
+            (200, HISTORICAL_RESPONSE),
+            (200, SIGNAL_INDEX_RESPONSE),
+        ])
+        .await;
+        let config = WattTime {
+            signal_type: SignalType::Co2Aoer,
+            ..config(&base_url, "abc")
+        };
+
+        let observation = Api::new(Client::new(), &config, TokenCache::default())
+            .carbon_intensity()
+            .await
+            .unwrap();
+
+        // the signal index would have been served next
+        assert_eq!(observation.percentile, None);
+        server.abort();
+    }
+
+    #[test]
+    fn test_to_observation() {
+        let response: data::api::WattTimeSignalResponse =
+            serde_json::from_str(HISTORICAL_RESPONSE).unwrap();
+
+        let observation = to_observation(response).unwrap();
+
+        assert!((observation.intensity.get::<pound_per_megawatt_hour>() - 401.0).abs() < 1e-9);
+        assert_eq!(observation.region, "CAISO_NORTH");
+        assert_eq!(
+            observation.observed_at,
+            Utc.with_ymd_and_hms(2023, 7, 18, 21, 55, 0).unwrap()
+        );
+        assert_eq!(
+            observation.valid_until,
+            Some(Utc.with_ymd_and_hms(2023, 7, 18, 22, 0, 0).unwrap())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_health_damage_keeps_unit() {
+        let (base_url, server) =
+            super::super::mock_server(vec![(200, LOGIN_RESPONSE), (200, HEALTH_DAMAGE_RESPONSE)])
+                .await;
+        let config = WattTime {
+            signal_type: SignalType::HealthDamage,
+            ..config(&base_url, "abc")
+        };
+
+        let provided = Api::new(Client::new(), &config, TokenCache::default())
+            .signal_value()
+            .await
+            .unwrap();
+
+        assert_eq!(provided.value.value, 12.5);
+        assert_eq!(provided.value.unit, "$_per_mwh");
+        assert_eq!(
+            provided.value.valid_until,
+            Some(Utc.with_ymd_and_hms(2023, 7, 18, 22, 0, 0).unwrap())
+        );
+        let requests = server.await.unwrap();
+        assert!(paths(&requests)[1]
+            .starts_with("/v3/historical?region=CAISO_NORTH&signal_type=health_damage&start="));
+    }
+
+    #[test]
+    fn test_to_forecast() {
+        let response: data::api::WattTimeSignalResponse =
+            serde_json::from_str(FORECAST_RESPONSE).unwrap();
+
+        let forecast = to_forecast(response).unwrap();
+
+        assert_eq!(forecast.points.len(), 2);
+        assert_eq!(
+            forecast.generated_at,
+            Utc.with_ymd_and_hms(2023, 7, 18, 21, 55, 0).unwrap()
+        );
+        assert_eq!(
+            forecast.points[1].end,
+            Utc.with_ymd_and_hms(2023, 7, 18, 22, 10, 0).unwrap()
+        );
+        let intensity = forecast.points[0]
+            .intensity
+            .get::<pound_per_megawatt_hour>();
+        assert!((intensity - 870.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_to_intensity_units() {
+        let grams = to_intensity(400.0, "g_co2_per_kwh").unwrap();
+        assert!((grams.get::<kilogram_per_megawatt_hour>() - 400.0).abs() < 1e-9);
+        assert!(to_intensity(400.0, "lbs_co2_per_mwh").is_ok());
+        assert_eq!(
+            to_intensity(12.0, "$_per_mwh").unwrap_err(),
+            APIError::Deserialze("unit $_per_mwh is not a carbon intensity".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_map_error() {
+        assert_eq!(
+            map_error(StatusCode::UNAUTHORIZED, "", "CAISO_NORTH"),
+            APIError::Authentication
+        );
+        assert_eq!(
+            map_error(StatusCode::BAD_REQUEST, "Invalid region XX", "XX"),
+            APIError::InvalidRegion("XX".to_owned())
+        );
+        assert_eq!(
+            map_error(StatusCode::FORBIDDEN, "Access denied for this signal", "XX"),
+            APIError::Unhandled("Access denied for this signal".to_owned())
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use std::path::PathBuf;
+
+use clap::{ArgGroup, Parser};
+
+use crate::{fs::CONFIG_PATH, ConfigSource};
+
+/// Operating-system daemon for carbon awareness.
+#[derive(Parser, Debug, PartialEq)]
+#[command(version, about)]
+#[command(group(ArgGroup::new("mode").args(["once", "check_config", "print_default_config", "print_effective_config"])))]
+pub struct Cli {
+    /// Path of the config file.
+    #[arg(short, long, value_name = "PATH", default_value = CONFIG_PATH)]
+    pub config: PathBuf,
+
+    /// Directory the metrics are published in, overrides the config.
+    #[arg(long, value_name = "PATH")]
+    pub data_root: Option<PathBuf>,
+
+    /// Increases the logging verbosity, overrides the config.
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    pub verbose: u8,
+
+    /// Runs every scheduled job a single time and exits.
+    #[arg(long)]
+    pub once: bool,
+
+    /// Checks the config file and exits.
+    #[arg(long)]
+    pub check_config: bool,
+
+    /// Prints the default config and exits.
+    #[arg(long)]
+    pub print_default_config: bool,
+
+    /// Prints the merged config with the origin of each value and exits.
+    #[arg(long)]
+    pub print_effective_config: bool,
+}
+
+impl Cli {
+    /// Where the config is loaded from and which values the command line overrides.
+    pub fn config_source(&self) -> ConfigSource {
+        ConfigSource {
+            path: self.config.clone(),
+            data_root: self.data_root.clone(),
+            logging_verbosity: (self.verbose > 0).then_some(self.verbose.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_defaults() {
+        let cli = Cli::try_parse_from(["carbond"]).unwrap();
+
+        assert_eq!(cli.config, PathBuf::from(CONFIG_PATH));
+        assert_eq!(cli.data_root, None);
+        assert_eq!(cli.verbose, 0);
+        assert!(!cli.once);
+    }
+
+    #[test]
+    fn test_parse_flags() {
+        let cli = Cli::try_parse_from([
+            "carbond",
+            "--config",
+            "/tmp/carbond.toml",
+            "--data-root",
+            "/tmp/carbond",
+            "-vv",
+            "--once",
+        ])
+        .unwrap();
+
+        assert_eq!(cli.config, PathBuf::from("/tmp/carbond.toml"));
+        assert_eq!(cli.data_root, Some(PathBuf::from("/tmp/carbond")));
+        assert_eq!(cli.verbose, 2);
+        assert!(cli.once);
+    }
+
+    #[test]
+    fn test_parse_conflicting_modes() {
+        assert!(Cli::try_parse_from(["carbond", "--once", "--check-config"]).is_err());
+    }
+}
@@ -1,40 +1,257 @@
+use carbond_lib::{fs::data_root, history::Retention};
+use reqwest::{Certificate, NoProxy, Proxy, Url};
 use uom::si::{f64::Mass, mass::gram};
 
 use crate::{
+    api::{electricity_map, national_grid, watt_time},
     data::config::{
-        external::{ConfigRepr, CpuConfigRepr},
-        internal::{Config, CpuConfig, DeviceConfig, ElectricityMap, WattTime},
+        external::{
+            ConfigRepr, CpuConfigRepr, ErrorPolicyRepr, HardeningRepr, HistoryRepr, HttpRepr,
+            IntensityServiceRepr, JobRepr, NationalGridRepr, ProviderRepr, SecretRepr,
+            SignalTypeRepr,
+        },
+        internal::{
+            Config, CpuConfig, DeviceConfig, ElectricityMap, ErrorPolicy, Hardening, HttpConfig,
+            JobPolicy, JobsConfig, NationalGrid, NationalGridRegion, ProviderKind, Schedule,
+            Secret, SignalType, WattTime,
+        },
     },
     errors::ConfigError,
 };
-use std::time::Duration;
+use std::{
+    os::unix::fs::MetadataExt,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+/// Interval of the history compaction if not scheduled otherwise.
+const HISTORY_COMPACTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Time to establish a connection to a provider if not configured otherwise.
+const HTTP_CONNECT_TIMEOUT: &str = "10s";
+
+/// Total time a request to a provider may take, from connecting until the
+/// response is read, if not configured otherwise.
+const HTTP_REQUEST_TIMEOUT: &str = "30s";
+
+/// Directory of the credentials passed by systemd with `LoadCredential`.
+pub(crate) const CREDENTIALS_DIRECTORY_ENV: &str = "CREDENTIALS_DIRECTORY";
+
+pub(crate) mod layers;
 
 impl Config {
     pub fn try_parse(raw: &str) -> Result<Self, ConfigError> {
-        let config: ConfigRepr = toml::from_str(raw)?;
+        Self::try_from_repr(toml::from_str(raw)?)
+    }
+
+    /// Converts a config merged from several layers.
+    pub(crate) fn try_from_table(table: toml::Table) -> Result<Self, ConfigError> {
+        Self::try_from_repr(table.try_into()?)
+    }
+
+    fn try_from_repr(config: ConfigRepr) -> Result<Self, ConfigError> {
+        let jobs = config.jobs.unwrap_or_default();
+        let secrets = SecretStore::load(config.secrets_file.as_deref())?;
+        let secret_files = secret_files(&config.intensity_service);
+        let update_interval = try_parse_interval(&config.update_interval)?;
+        let forecast_interval = config
+            .forecast_interval
+            .as_deref()
+            .map(try_parse_interval)
+            .transpose()?;
+        let carbon_intensity_job =
+            try_parse_job_policy(jobs.carbon_intensity, Some(update_interval))?;
+        let reports_health_damage = config
+            .intensity_service
+            .watt_time
+            .as_ref()
+            .is_some_and(|wt| matches!(wt.signal_type, Some(SignalTypeRepr::HealthDamage)));
+        if reports_health_damage
+            && config.intensity_service.electricity_map.is_none()
+            && config.intensity_service.national_grid.is_none()
+        {
+            return Err(ConfigError::ConfigMissing(String::from(
+                "A config for electricity_map or national_grid, which provides the carbon intensity while watt_time reports the health damage",
+            )));
+        }
         let config = Config {
             logging_verbosity: config.logging_verbosity.unwrap_or(0),
-            update_interval: try_parse_interval(&config.update_interval)?,
+            update_interval,
+            forecast_interval,
+            data_root: config
+                .data_root
+                .map(PathBuf::from)
+                .unwrap_or_else(data_root),
+            shutdown_timeout: try_parse_interval(
+                config.shutdown_timeout.as_deref().unwrap_or("30s"),
+            )?,
+            user: config.user,
+            group: config.group,
+            secrets_file: config.secrets_file.map(PathBuf::from),
+            secret_files,
+            hardening: match config.hardening.unwrap_or(HardeningRepr::Off) {
+                HardeningRepr::Off => Hardening::Off,
+                HardeningRepr::BestEffort => Hardening::BestEffort,
+                HardeningRepr::Required => Hardening::Required,
+            },
+            http: try_parse_http(config.http.unwrap_or_default())?,
+            provider_priority: try_parse_priority(&config.intensity_service)?,
             electricity_map: config
                 .intensity_service
                 .electricity_map
-                .map(|em| ElectricityMap {
-                    region: em.region,
-                    token: em.token,
-                }),
-            watt_time: config.intensity_service.watt_time.map(|wt| WattTime {
-                region: wt.region,
-                username: wt.username,
-                password: wt.password,
-            }),
+                .map(|em| {
+                    if !electricity_map::is_valid_zone(&em.region) {
+                        return Err(ConfigError::InvalidRegion(em.region, "electricity_map"));
+                    }
+                    Ok(ElectricityMap {
+                        region: em.region,
+                        base_url: try_parse_base_url(em.base_url, electricity_map::BASE_URL)?,
+                        token: secrets.resolve("electricity_map", "token", em.token)?,
+                    })
+                })
+                .transpose()?,
+            watt_time: config
+                .intensity_service
+                .watt_time
+                .map(|wt| {
+                    Ok::<_, ConfigError>(WattTime {
+                        region: wt.region,
+                        base_url: try_parse_base_url(wt.base_url, watt_time::BASE_URL)?,
+                        signal_type: match wt.signal_type.unwrap_or(SignalTypeRepr::Co2Moer) {
+                            SignalTypeRepr::Co2Moer => SignalType::Co2Moer,
+                            SignalTypeRepr::Co2Aoer => SignalType::Co2Aoer,
+                            SignalTypeRepr::HealthDamage => SignalType::HealthDamage,
+                        },
+                        username: secrets.resolve("watt_time", "username", wt.username)?,
+                        password: secrets.resolve("watt_time", "password", wt.password)?,
+                    })
+                })
+                .transpose()?,
+            national_grid: config
+                .intensity_service
+                .national_grid
+                .map(try_parse_national_grid)
+                .transpose()?,
             device_config: config.device.map(|dc| DeviceConfig {
                 cpu: parse_cpu_config(dc.cpu),
             }),
+            jobs: JobsConfig {
+                health_damage: JobPolicy {
+                    schedule: carbon_intensity_job
+                        .schedule
+                        .clone()
+                        .filter(|_| reports_health_damage),
+                    ..carbon_intensity_job.clone()
+                },
+                carbon_intensity: carbon_intensity_job,
+                forecast: try_parse_job_policy(jobs.forecast, forecast_interval)?,
+                history: try_parse_job_policy(jobs.history, Some(HISTORY_COMPACTION_INTERVAL))?,
+            },
+            history: try_parse_retention(config.history.unwrap_or_default())?,
         };
         Ok(config)
     }
 }
 
+/// Secrets that are not given in the config itself, either as systemd credentials
+/// or in a separate secrets file that is only readable by its owner.
+struct SecretStore {
+    credentials: Option<PathBuf>,
+    file: Option<toml::Table>,
+}
+
+impl SecretStore {
+    fn load(secrets_file: Option<&str>) -> Result<Self, ConfigError> {
+        let file = secrets_file
+            .map(|path| {
+                check_owner_only(Path::new(path))?;
+                let raw = read_secret(path)?;
+                toml::from_str(&raw).map_err(ConfigError::from)
+            })
+            .transpose()?;
+        Ok(SecretStore {
+            credentials: std::env::var_os(CREDENTIALS_DIRECTORY_ENV).map(PathBuf::from),
+            file,
+        })
+    }
+
+    /// Resolves the secret `key` of a provider `section`.
+    /// Secrets missing in the config are looked up as systemd credential `<section>_<key>`
+    /// and in the secrets file under `[section] key`.
+    fn resolve(
+        &self,
+        section: &str,
+        key: &str,
+        secret: Option<SecretRepr>,
+    ) -> Result<Secret, ConfigError> {
+        let name = format!("{section}.{key}");
+        let value = match secret {
+            Some(SecretRepr::Plain(value)) => value,
+            Some(SecretRepr::File { file }) => read_secret(&file)?,
+            Some(SecretRepr::Env { env }) => std::env::var(&env)
+                .map_err(|_| ConfigError::ReadSecret(name, format!("{env} is not set")))?,
+            None => match self.lookup(section, key)? {
+                Some(value) => value,
+                None => return Err(ConfigError::ConfigMissing(name)),
+            },
+        };
+        Ok(Secret::new(value))
+    }
+
+    fn lookup(&self, section: &str, key: &str) -> Result<Option<String>, ConfigError> {
+        if let Some(credentials) = &self.credentials {
+            let path = credentials.join(format!("{section}_{key}"));
+            if path.is_file() {
+                return read_secret(&path.to_string_lossy()).map(Some);
+            }
+        }
+        Ok(self
+            .file
+            .as_ref()
+            .and_then(|file| file.get(section)?.get(key)?.as_str())
+            .map(str::to_owned))
+    }
+}
+
+/// Lists the files of secrets given as `{ file = "..." }`.
+fn secret_files(service: &IntensityServiceRepr) -> Vec<PathBuf> {
+    let electricity_map = service.electricity_map.iter().map(|em| &em.token);
+    let watt_time = service
+        .watt_time
+        .iter()
+        .flat_map(|wt| [&wt.username, &wt.password]);
+    electricity_map
+        .chain(watt_time)
+        .filter_map(|secret| match secret {
+            Some(SecretRepr::File { file }) => Some(PathBuf::from(file)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Reads a secret from a file without its trailing line break.
+fn read_secret(path: &str) -> Result<String, ConfigError> {
+    let secret = std::fs::read_to_string(path)
+        .map_err(|e| ConfigError::ReadSecret(path.to_owned(), e.to_string()))?;
+    Ok(secret.trim_end_matches(['\r', '\n']).to_owned())
+}
+
+/// Checks that a file is owned by root or carbond's user and not accessible by anyone else.
+/// Files owned by root may also be readable by their group.
+fn check_owner_only(path: &Path) -> Result<(), ConfigError> {
+    let metadata = std::fs::metadata(path)
+        .map_err(|e| ConfigError::ReadSecret(path.display().to_string(), e.to_string()))?;
+    let own_uid = std::fs::metadata("/proc/self").map(|proc| proc.uid()).ok();
+    let owner_allowed = metadata.uid() == 0 || Some(metadata.uid()) == own_uid;
+    // root shares secrets with the group carbond runs as after dropping privileges
+    let denied_mode = if metadata.uid() == 0 { 0o037 } else { 0o077 };
+    if !owner_allowed || metadata.mode() & denied_mode != 0 {
+        return Err(ConfigError::InsecureSecrets(path.display().to_string()));
+    }
+    Ok(())
+}
+
 fn parse_cpu_config(config: Option<CpuConfigRepr>) -> Option<CpuConfig> {
     config.map(|f| CpuConfig {
         embodied_g: Mass::new::<gram>(f.embodied_g),
@@ -42,40 +259,243 @@ fn parse_cpu_config(config: Option<CpuConfigRepr>) -> Option<CpuConfig> {
     })
 }
 
-fn try_parse_interval(interval: &str) -> Result<Duration, ConfigError> {
-    #[allow(clippy::unwrap_used)]
-    let re = regex::Regex::new("^(\\d+)([smh])$").unwrap();
-    let capture = re
-        .captures(interval)
-        .ok_or(ConfigError::ParseInterval(interval.to_owned()))?;
-    let time: u64 = capture
-        .get(1)
-        .ok_or(ConfigError::ParseInterval(interval.to_owned()))?
-        .as_str()
-        .parse()
-        .map_err(|_| ConfigError::ParseInterval(interval.to_owned()))?;
-    if time == 0 {
-        return Err(ConfigError::ParseInterval(interval.to_owned()));
-    }
-    let unit: char = capture
-        .get(2)
-        .ok_or(ConfigError::ParseInterval(interval.to_owned()))?
-        .as_str()
-        .parse()
-        .map_err(|_| ConfigError::ParseInterval(interval.to_owned()))?;
-    let duration = match unit {
-        'h' => Duration::from_secs(time * 60 * 60),
-        'm' => Duration::from_secs(time * 60),
-        's' => Duration::from_secs(time),
-        _ => return Err(ConfigError::ParseInterval(interval.to_owned())),
+/// Builds the HTTP client shared by all providers.
+/// Without a configured `proxy`, the proxy is taken from `HTTPS_PROXY`, `HTTP_PROXY` and `NO_PROXY`.
+fn try_parse_http(http: HttpRepr) -> Result<HttpConfig, ConfigError> {
+    let ca_certificates: Vec<PathBuf> = http
+        .ca_certificates
+        .unwrap_or_default()
+        .into_iter()
+        .map(PathBuf::from)
+        .collect();
+    let user_agent = http
+        .user_agent
+        .unwrap_or_else(|| format!("carbond/{}", env!("CARGO_PKG_VERSION")));
+    let mut builder = reqwest::Client::builder()
+        .connect_timeout(try_parse_interval(
+            http.connect_timeout
+                .as_deref()
+                .unwrap_or(HTTP_CONNECT_TIMEOUT),
+        )?)
+        .timeout(try_parse_interval(
+            http.request_timeout
+                .as_deref()
+                .unwrap_or(HTTP_REQUEST_TIMEOUT),
+        )?)
+        .user_agent(user_agent);
+    if let Some(proxy) = http.proxy {
+        let no_proxy = http
+            .no_proxy
+            .and_then(|hosts| NoProxy::from_string(&hosts.join(",")));
+        let proxy = Proxy::all(&proxy).map_err(|_| ConfigError::InvalidUrl(proxy.clone()))?;
+        builder = builder.proxy(proxy.no_proxy(no_proxy));
+    }
+    for path in &ca_certificates {
+        let error = |e: String| ConfigError::ReadCertificate(path.display().to_string(), e);
+        let pem = std::fs::read(path).map_err(|e| error(e.to_string()))?;
+        let certificate = Certificate::from_pem(&pem).map_err(|e| error(e.to_string()))?;
+        builder = builder.add_root_certificate(certificate);
+    }
+    let client = builder
+        .build()
+        .map_err(|e| ConfigError::HttpClient(e.to_string()))?;
+    Ok(HttpConfig {
+        client,
+        ca_certificates,
+    })
+}
+
+/// Parses the base URL of a provider, defaults to its public API.
+fn try_parse_base_url(base_url: Option<String>, default: &str) -> Result<String, ConfigError> {
+    let Some(base_url) = base_url else {
+        return Ok(default.to_owned());
     };
+    match Url::parse(&base_url) {
+        Ok(url) if matches!(url.scheme(), "http" | "https") => {
+            Ok(base_url.trim_end_matches('/').to_owned())
+        }
+        _ => Err(ConfigError::InvalidUrl(base_url)),
+    }
+}
+
+/// Parses the region of the National Grid API, which is either given by postcode or region id.
+fn try_parse_national_grid(national_grid: NationalGridRepr) -> Result<NationalGrid, ConfigError> {
+    let region = match (national_grid.postcode, national_grid.region_id) {
+        (Some(postcode), None) => NationalGridRegion::Postcode(
+            national_grid::outward_code(&postcode)
+                .ok_or(ConfigError::InvalidRegion(postcode, "national_grid"))?,
+        ),
+        (None, Some(region_id)) if (1..=18).contains(&region_id) => {
+            NationalGridRegion::RegionId(region_id)
+        }
+        (None, Some(region_id)) => {
+            return Err(ConfigError::InvalidRegion(
+                region_id.to_string(),
+                "national_grid",
+            ))
+        }
+        (Some(_), Some(_)) => {
+            return Err(ConfigError::ConflictingOptions(String::from(
+                "national_grid.postcode and national_grid.region_id",
+            )))
+        }
+        (None, None) => {
+            return Err(ConfigError::ConfigMissing(String::from(
+                "national_grid.postcode or national_grid.region_id",
+            )))
+        }
+    };
+    Ok(NationalGrid {
+        region,
+        base_url: try_parse_base_url(national_grid.base_url, national_grid::BASE_URL)?,
+    })
+}
 
-    Ok(duration)
+/// Parses the order in which intensity providers are requested.
+/// Defaults to WattTime before Electricity Maps before National Grid.
+fn try_parse_priority(service: &IntensityServiceRepr) -> Result<Vec<ProviderKind>, ConfigError> {
+    let priority = service.priority.clone().unwrap_or(vec![
+        ProviderRepr::WattTime,
+        ProviderRepr::ElectricityMap,
+        ProviderRepr::NationalGrid,
+    ]);
+    let mut kinds = vec![];
+    for provider in priority {
+        let (kind, configured, name) = match provider {
+            ProviderRepr::WattTime => (
+                ProviderKind::WattTime,
+                service.watt_time.is_some(),
+                "watt_time",
+            ),
+            ProviderRepr::ElectricityMap => (
+                ProviderKind::ElectricityMap,
+                service.electricity_map.is_some(),
+                "electricity_map",
+            ),
+            ProviderRepr::NationalGrid => (
+                ProviderKind::NationalGrid,
+                service.national_grid.is_some(),
+                "national_grid",
+            ),
+        };
+        if configured {
+            kinds.push(kind);
+        } else if service.priority.is_some() {
+            return Err(ConfigError::ConfigMissing(format!(
+                "A config for prioritized provider {name} is missing"
+            )));
+        }
+    }
+    Ok(kinds)
+}
+
+/// Parses the schedule and error policy of a job.
+/// Jobs without a schedule run at their default interval or are paused if there is none.
+/// Errors are retried 3 times with delays from 10s up to 5m by default,
+/// and values do not have a maximum staleness.
+fn try_parse_job_policy(
+    job: Option<JobRepr>,
+    default_interval: Option<Duration>,
+) -> Result<JobPolicy, ConfigError> {
+    let job = job.unwrap_or_default();
+    let aligned = job.align.unwrap_or(false);
+    let schedule = match job.schedule.as_deref() {
+        Some(schedule) => Some(try_parse_schedule(schedule, aligned)?),
+        None => default_interval.map(|period| Schedule::Every { period, aligned }),
+    };
+    let on_error = match job.on_error.unwrap_or(ErrorPolicyRepr::Retry) {
+        ErrorPolicyRepr::Retry => ErrorPolicy::Retry {
+            max_retries: job.max_retries.unwrap_or(3),
+            base_delay: try_parse_interval(job.retry_delay.as_deref().unwrap_or("10s"))?,
+            max_delay: try_parse_interval(job.max_retry_delay.as_deref().unwrap_or("5m"))?,
+        },
+        ErrorPolicyRepr::Skip => ErrorPolicy::Skip,
+        ErrorPolicyRepr::Fatal => ErrorPolicy::Fatal,
+    };
+    let max_staleness = job
+        .max_staleness
+        .as_deref()
+        .map(try_parse_interval)
+        .transpose()?;
+    Ok(JobPolicy {
+        schedule,
+        on_error,
+        max_staleness,
+    })
+}
+
+/// Parses the retention of the intensity history.
+/// Defaults to 7 days of raw values, 90 days of hourly aggregates and
+/// keeping daily aggregates forever.
+fn try_parse_retention(history: HistoryRepr) -> Result<Retention, ConfigError> {
+    let parse = |interval: &str| {
+        let duration = try_parse_interval(interval)?;
+        chrono::Duration::from_std(duration)
+            .map_err(|_| ConfigError::ParseInterval(interval.to_owned()))
+    };
+    Ok(Retention {
+        raw: parse(history.raw_retention.as_deref().unwrap_or("168h"))?,
+        hourly: parse(history.hourly_retention.as_deref().unwrap_or("2160h"))?,
+        daily: history.daily_retention.as_deref().map(parse).transpose()?,
+    })
+}
+
+/// Parses durations like `90s`, `1h30m`, `1d 12h` or `500ms`.
+/// Supported units are `ms`, `s`, `m`, `h` and `d`, the total must not be zero.
+fn try_parse_interval(interval: &str) -> Result<Duration, ConfigError> {
+    let error = || ConfigError::ParseInterval(interval.to_owned());
+    let mut rest = interval.trim();
+    let mut total = Duration::ZERO;
+    while !rest.is_empty() {
+        let digits = rest.find(|c: char| !c.is_ascii_digit()).ok_or_else(error)?;
+        let (number, tail) = rest.split_at(digits);
+        let value: u64 = number.parse().map_err(|_| error())?;
+        let unit_len = tail
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        let unit_millis = match unit {
+            "ms" => 1,
+            "s" => 1000,
+            "m" => 60 * 1000,
+            "h" => 60 * 60 * 1000,
+            "d" => 24 * 60 * 60 * 1000,
+            _ => return Err(error()),
+        };
+        let part = value
+            .checked_mul(unit_millis)
+            .map(Duration::from_millis)
+            .ok_or_else(error)?;
+        total = total.checked_add(part).ok_or_else(error)?;
+        rest = tail.trim_start();
+    }
+    if total.is_zero() {
+        return Err(error());
+    }
+    Ok(total)
+}
+
+/// Parses a job schedule, either an interval or a cron expression evaluated in UTC.
+/// Cron expressions have five fields or six to seven fields starting with seconds.
+/// Aligned intervals tick on multiples of their period since the Unix epoch,
+/// cron expressions are aligned by definition.
+fn try_parse_schedule(schedule: &str, aligned: bool) -> Result<Schedule, ConfigError> {
+    if let Ok(period) = try_parse_interval(schedule) {
+        return Ok(Schedule::Every { period, aligned });
+    }
+    let expression = match schedule.split_whitespace().count() {
+        5 => format!("0 {schedule}"),
+        _ => schedule.to_owned(),
+    };
+    cron::Schedule::from_str(&expression)
+        .map(|cron| Schedule::Cron(Box::new(cron)))
+        .map_err(|_| ConfigError::ParseSchedule(schedule.to_owned()))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use tempfile::TempDir;
 
     #[test]
     fn test_try_parse_interval() {
@@ -97,6 +517,22 @@ mod tests {
         );
         assert_eq!(try_parse_interval("2s").unwrap(), Duration::from_secs(2));
         assert_eq!(try_parse_interval("52s").unwrap(), Duration::from_secs(52));
+        assert_eq!(
+            try_parse_interval("2d").unwrap(),
+            Duration::from_secs(2 * 24 * 60 * 60)
+        );
+        assert_eq!(
+            try_parse_interval("1h30m").unwrap(),
+            Duration::from_secs(90 * 60)
+        );
+        assert_eq!(
+            try_parse_interval("1d 12h").unwrap(),
+            Duration::from_secs(36 * 60 * 60)
+        );
+        assert_eq!(
+            try_parse_interval("500ms").unwrap(),
+            Duration::from_millis(500)
+        );
     }
 
     #[test]
@@ -113,6 +549,35 @@ mod tests {
             try_parse_interval("5days").unwrap_err(),
             ConfigError::ParseInterval("5days".to_owned())
         );
+        for invalid in ["", "5", "h", "1h30", "0m0s", "1.5h"] {
+            assert_eq!(
+                try_parse_interval(invalid).unwrap_err(),
+                ConfigError::ParseInterval(invalid.to_owned())
+            );
+        }
+    }
+
+    #[test]
+    fn test_try_parse_schedule() {
+        assert_eq!(
+            try_parse_schedule("5m", true).unwrap(),
+            Schedule::Every {
+                period: Duration::from_secs(5 * 60),
+                aligned: true,
+            }
+        );
+        let Schedule::Cron(cron) = try_parse_schedule("*/5 * * * *", false).unwrap() else {
+            panic!("expected cron schedule");
+        };
+        assert_eq!(cron.to_string(), "0 */5 * * * *");
+        assert!(matches!(
+            try_parse_schedule("0 30 6 * * Mon-Fri", false).unwrap(),
+            Schedule::Cron(_)
+        ));
+        assert_eq!(
+            try_parse_schedule("every 5 minutes", false).unwrap_err(),
+            ConfigError::ParseSchedule("every 5 minutes".to_owned())
+        );
     }
 
     #[test]
@@ -123,7 +588,7 @@ mod tests {
         [intensity_service]
 
         [intensity_service.electricity_map]
-        region = "France"
+        region = "FR"
         token = "123"
 
         [intensity_service.watt_time]
@@ -135,21 +600,456 @@ mod tests {
         let config = Config::try_parse(raw_config).unwrap();
 
         assert_eq!(config.update_interval, Duration::from_secs(60 * 60));
+        assert_eq!(config.forecast_interval, None);
+        assert_eq!(config.data_root, data_root());
+        assert_eq!(config.shutdown_timeout, Duration::from_secs(30));
         assert_eq!(
-            config.electricity_map.as_ref().unwrap().token,
-            "123".to_owned()
+            config.electricity_map.as_ref().unwrap().token.expose(),
+            "123"
         );
         assert_eq!(
             config.electricity_map.as_ref().unwrap().region,
-            "France".to_owned()
+            "FR".to_owned()
+        );
+        assert_eq!(config.watt_time.as_ref().unwrap().username.expose(), "abc");
+        assert_eq!(config.watt_time.as_ref().unwrap().password.expose(), "dce");
+        assert_eq!(
+            config.watt_time.as_ref().unwrap().signal_type,
+            SignalType::Co2Moer
+        );
+        let config = Config::try_parse(&raw_config.replace(
+            "region = \"Germany\"",
+            "region = \"Germany\"\nsignal_type = \"co2_aoer\"",
+        ))
+        .unwrap();
+        assert_eq!(config.watt_time.unwrap().signal_type, SignalType::Co2Aoer);
+        assert!(config.jobs.health_damage.schedule.is_none());
+        let config = Config::try_parse(&raw_config.replace(
+            "region = \"Germany\"",
+            "region = \"Germany\"\nsignal_type = \"health_damage\"",
+        ))
+        .unwrap();
+        assert_eq!(
+            config.watt_time.as_ref().unwrap().signal_type,
+            SignalType::HealthDamage
+        );
+        assert_eq!(
+            config.jobs.health_damage.schedule,
+            config.jobs.carbon_intensity.schedule
+        );
+        let without_intensity = raw_config
+            .replace("[intensity_service.electricity_map]", "")
+            .replace("region = \"FR\"", "")
+            .replace("token = \"123\"", "")
+            .replace(
+                "region = \"Germany\"",
+                "region = \"Germany\"\nsignal_type = \"health_damage\"",
+            );
+        assert!(matches!(
+            Config::try_parse(&without_intensity),
+            Err(ConfigError::ConfigMissing(_))
+        ));
+        assert_eq!(
+            config.provider_priority,
+            vec![ProviderKind::WattTime, ProviderKind::ElectricityMap]
+        );
+    }
+
+    #[test]
+    fn test_config_try_parse_job_policy() {
+        let raw_config = r#"
+        update_interval = "1h"
+        forecast_interval = "6h"
+
+        [intensity_service]
+
+        [intensity_service.watt_time]
+        region = "Germany"
+        username = "abc"
+        password = "dce"
+
+        [jobs.forecast]
+        on_error = "skip"
+
+        [jobs.carbon_intensity]
+        on_error = "retry"
+        max_retries = 5
+        retry_delay = "1s"
+        max_staleness = "6h"
+        "#;
+
+        let config = Config::try_parse(raw_config).unwrap();
+
+        assert_eq!(
+            config.jobs.carbon_intensity,
+            JobPolicy {
+                schedule: Some(Schedule::Every {
+                    period: Duration::from_secs(60 * 60),
+                    aligned: false,
+                }),
+                on_error: ErrorPolicy::Retry {
+                    max_retries: 5,
+                    base_delay: Duration::from_secs(1),
+                    max_delay: Duration::from_secs(5 * 60),
+                },
+                max_staleness: Some(Duration::from_secs(6 * 60 * 60)),
+            }
+        );
+        assert_eq!(
+            config.forecast_interval,
+            Some(Duration::from_secs(6 * 60 * 60))
         );
+        assert_eq!(config.jobs.forecast.on_error, ErrorPolicy::Skip);
+    }
+
+    #[test]
+    fn test_config_try_parse_job_schedule() {
+        let raw_config = r#"
+        update_interval = "1h"
+
+        [intensity_service]
+
+        [jobs.carbon_intensity]
+        schedule = "5m"
+        align = true
+
+        [jobs.history]
+        schedule = "0 3 * * *"
+        "#;
+
+        let config = Config::try_parse(raw_config).unwrap();
+
         assert_eq!(
-            config.watt_time.as_ref().unwrap().username,
-            "abc".to_owned()
+            config.jobs.carbon_intensity.schedule,
+            Some(Schedule::Every {
+                period: Duration::from_secs(5 * 60),
+                aligned: true,
+            })
         );
+        assert_eq!(config.jobs.forecast.schedule, None);
+        assert!(matches!(
+            config.jobs.history.schedule,
+            Some(Schedule::Cron(_))
+        ));
+    }
+
+    #[test]
+    fn test_config_try_parse_history() {
+        let raw_config = r#"
+        update_interval = "1h"
+
+        [intensity_service]
+
+        [history]
+        raw_retention = "24h"
+        daily_retention = "8760h"
+        "#;
+
+        let config = Config::try_parse(raw_config).unwrap();
+
         assert_eq!(
-            config.watt_time.as_ref().unwrap().password,
-            "dce".to_owned()
+            config.history,
+            Retention {
+                raw: chrono::Duration::hours(24),
+                hourly: chrono::Duration::days(90),
+                daily: Some(chrono::Duration::days(365)),
+            }
         );
     }
+
+    #[test]
+    fn test_config_try_parse_data_root() {
+        let raw_config = r#"
+        update_interval = "1h"
+        data_root = "/tmp/carbond"
+
+        [intensity_service]
+        "#;
+
+        let config = Config::try_parse(raw_config).unwrap();
+
+        assert_eq!(config.data_root, PathBuf::from("/tmp/carbond"));
+    }
+
+    #[test]
+    fn test_config_try_parse_hardening() {
+        let raw_config = r#"
+        update_interval = "1h"
+        hardening = "best_effort"
+
+        [intensity_service]
+        "#;
+
+        let config = Config::try_parse(raw_config).unwrap();
+
+        assert_eq!(config.hardening, Hardening::BestEffort);
+        assert!(Config::try_parse(&raw_config.replace("best_effort", "strict")).is_err());
+    }
+
+    #[test]
+    fn test_config_try_parse_http() {
+        let raw_config = r#"
+        update_interval = "1h"
+
+        [http]
+        connect_timeout = "5s"
+        request_timeout = "1m"
+        proxy = "http://proxy.example.com:3128"
+        no_proxy = ["localhost", ".internal"]
+        user_agent = "carbond-test"
+
+        [intensity_service.electricity_map]
+        region = "DE"
+        token = "123"
+        base_url = "http://127.0.0.1:8080/v3/"
+
+        [intensity_service.watt_time]
+        region = "CAISO_NORTH"
+        username = "abc"
+        password = "dce"
+        "#;
+
+        let config = Config::try_parse(raw_config).unwrap();
+
+        assert_eq!(
+            config.electricity_map.unwrap().base_url,
+            "http://127.0.0.1:8080/v3"
+        );
+        assert_eq!(config.watt_time.unwrap().base_url, watt_time::BASE_URL);
+        assert!(config.http.ca_certificates.is_empty());
+        assert_eq!(
+            Config::try_parse(&raw_config.replace("http://127.0.0.1:8080/v3/", "localhost"))
+                .unwrap_err(),
+            ConfigError::InvalidUrl("localhost".to_owned())
+        );
+        assert!(Config::try_parse(&raw_config.replace("\"1m\"", "\"soon\"")).is_err());
+    }
+
+    #[test]
+    fn test_config_try_parse_http_certificates() {
+        let dir = TempDir::new().unwrap();
+        let certificate = dir.path().join("ca.pem");
+        let raw_config = format!(
+            r#"
+        update_interval = "1h"
+
+        [http]
+        ca_certificates = ["{}"]
+
+        [intensity_service]
+        "#,
+            certificate.display()
+        );
+
+        assert!(matches!(
+            Config::try_parse(&raw_config).unwrap_err(),
+            ConfigError::ReadCertificate(..)
+        ));
+        std::fs::write(&certificate, "not a certificate").unwrap();
+        assert!(matches!(
+            Config::try_parse(&raw_config).unwrap_err(),
+            ConfigError::ReadCertificate(..)
+        ));
+    }
+
+    #[test]
+    fn test_config_try_parse_secret_sources() {
+        let dir = TempDir::new().unwrap();
+        let password_file = dir.path().join("password");
+        std::fs::write(&password_file, "dce\n").unwrap();
+        let env = format!("CARBOND_TEST_USERNAME_{}", fastrand::u64(..));
+        std::env::set_var(&env, "abc");
+        let raw_config = format!(
+            r#"
+        update_interval = "1h"
+
+        [intensity_service.watt_time]
+        region = "CAISO_NORTH"
+        username = {{ env = "{env}" }}
+        password = {{ file = "{}" }}
+        "#,
+            password_file.display()
+        );
+
+        let config = Config::try_parse(&raw_config).unwrap();
+
+        assert_eq!(config.secret_files, vec![password_file]);
+        let watt_time = config.watt_time.unwrap();
+        assert_eq!(watt_time.username.expose(), "abc");
+        assert_eq!(watt_time.password.expose(), "dce");
+        assert_eq!(format!("{:?}", watt_time.password), "Secret([redacted])");
+    }
+
+    #[test]
+    fn test_config_try_parse_secrets_file() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = TempDir::new().unwrap();
+        let secrets_file = dir.path().join("secrets");
+        std::fs::write(&secrets_file, "[electricity_map]\ntoken = \"123\"\n").unwrap();
+        let raw_config = format!(
+            r#"
+        update_interval = "1h"
+        secrets_file = "{}"
+
+        [intensity_service.electricity_map]
+        region = "FR"
+        "#,
+            secrets_file.display()
+        );
+
+        std::fs::set_permissions(&secrets_file, std::fs::Permissions::from_mode(0o644)).unwrap();
+        assert!(matches!(
+            Config::try_parse(&raw_config).unwrap_err(),
+            ConfigError::InsecureSecrets(_)
+        ));
+
+        std::fs::set_permissions(&secrets_file, std::fs::Permissions::from_mode(0o640)).unwrap();
+        let owned_by_root = std::fs::metadata(&secrets_file).unwrap().uid() == 0;
+        assert_eq!(Config::try_parse(&raw_config).is_ok(), owned_by_root);
+
+        std::fs::set_permissions(&secrets_file, std::fs::Permissions::from_mode(0o600)).unwrap();
+        let config = Config::try_parse(&raw_config).unwrap();
+        assert_eq!(config.electricity_map.unwrap().token.expose(), "123");
+    }
+
+    #[test]
+    fn test_config_try_parse_missing_secret() {
+        let raw_config = r#"
+        update_interval = "1h"
+
+        [intensity_service.electricity_map]
+        region = "FR"
+        "#;
+
+        assert_eq!(
+            Config::try_parse(raw_config).unwrap_err(),
+            ConfigError::ConfigMissing("electricity_map.token".to_owned())
+        );
+    }
+
+    #[test]
+    fn test_config_try_parse_invalid_zone() {
+        let raw_config = r#"
+        update_interval = "1h"
+
+        [intensity_service.electricity_map]
+        region = "France"
+        token = "123"
+        "#;
+
+        assert_eq!(
+            Config::try_parse(raw_config).unwrap_err(),
+            ConfigError::InvalidRegion("France".to_owned(), "electricity_map")
+        );
+    }
+
+    #[test]
+    fn test_config_try_parse_priority() {
+        let raw_config = r#"
+        update_interval = "1h"
+
+        [intensity_service]
+        priority = ["electricity_map", "watt_time"]
+
+        [intensity_service.electricity_map]
+        region = "FR"
+        token = "123"
+
+        [intensity_service.watt_time]
+        region = "Germany"
+        username = "abc"
+        password = "dce"
+        "#;
+
+        let config = Config::try_parse(raw_config).unwrap();
+
+        assert_eq!(
+            config.provider_priority,
+            vec![ProviderKind::ElectricityMap, ProviderKind::WattTime]
+        );
+    }
+
+    #[test]
+    fn test_config_try_parse_national_grid() {
+        let raw_config = r#"
+        update_interval = "1h"
+
+        [intensity_service.national_grid]
+        postcode = "RG10"
+        "#;
+
+        let config = Config::try_parse(raw_config).unwrap();
+
+        let national_grid = config.national_grid.unwrap();
+        assert_eq!(
+            national_grid.region,
+            NationalGridRegion::Postcode("RG10".to_owned())
+        );
+        assert_eq!(national_grid.base_url, national_grid::BASE_URL);
+        assert_eq!(config.provider_priority, vec![ProviderKind::NationalGrid]);
+        assert_eq!(
+            Config::try_parse(&raw_config.replace("postcode = \"RG10\"", "region_id = 16"))
+                .unwrap()
+                .national_grid
+                .unwrap()
+                .region,
+            NationalGridRegion::RegionId(16)
+        );
+        assert!(matches!(
+            Config::try_parse(&format!("{raw_config}region_id = 16\n")).unwrap_err(),
+            ConfigError::ConflictingOptions(_)
+        ));
+        assert!(matches!(
+            Config::try_parse(&raw_config.replace("postcode = \"RG10\"", "")).unwrap_err(),
+            ConfigError::ConfigMissing(_)
+        ));
+    }
+
+    #[test]
+    fn test_config_try_parse_national_grid_region() {
+        let raw_config = r#"
+        update_interval = "1h"
+
+        [intensity_service.national_grid]
+        postcode = "rg10 1aa"
+        "#;
+
+        assert_eq!(
+            Config::try_parse(raw_config)
+                .unwrap()
+                .national_grid
+                .unwrap()
+                .region,
+            NationalGridRegion::Postcode("RG10".to_owned())
+        );
+        assert_eq!(
+            Config::try_parse(&raw_config.replace("rg10 1aa", "../intensity")).unwrap_err(),
+            ConfigError::InvalidRegion("../intensity".to_owned(), "national_grid")
+        );
+        assert_eq!(
+            Config::try_parse(&raw_config.replace("postcode = \"rg10 1aa\"", "region_id = 19"))
+                .unwrap_err(),
+            ConfigError::InvalidRegion("19".to_owned(), "national_grid")
+        );
+    }
+
+    #[test]
+    fn test_config_try_parse_priority_missing_provider() {
+        let raw_config = r#"
+        update_interval = "1h"
+
+        [intensity_service]
+        priority = ["electricity_map"]
+
+        [intensity_service.watt_time]
+        region = "Germany"
+        username = "abc"
+        password = "dce"
+        "#;
+
+        assert!(matches!(
+            Config::try_parse(raw_config).unwrap_err(),
+            ConfigError::ConfigMissing(_)
+        ));
+    }
 }
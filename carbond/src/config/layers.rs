@@ -0,0 +1,335 @@
+use std::{collections::BTreeMap, fmt::Display, path::PathBuf};
+
+use toml::{Table, Value};
+
+use crate::errors::ConfigError;
+
+/// Prefix of environment variables overriding config keys.
+const ENV_PREFIX: &str = "CARBOND_";
+
+/// Separator of nested keys in environment variables,
+/// e.g. `CARBOND_INTENSITY_SERVICE__WATT_TIME__REGION`.
+const ENV_SEPARATOR: &str = "__";
+
+/// Keys whose values are not shown in the effective config.
+const SECRET_KEYS: &[&str] = &["token", "username", "password"];
+
+/// Dotted keys whose values are not strings, `*` matches any key.
+/// Environment variables setting them are parsed as toml, all others are taken as string.
+const NON_STRING_KEYS: &[&str] = &[
+    "logging_verbosity",
+    "intensity_service.priority",
+    "intensity_service.national_grid.region_id",
+    "http.no_proxy",
+    "http.ca_certificates",
+    "device.cpu.embodied_g",
+    "device.cpu.lifetime_cycles",
+    "jobs.*.align",
+    "jobs.*.max_retries",
+];
+
+/// Where a config value was set.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Origin {
+    File(PathBuf),
+    Env(String),
+    CommandLine,
+}
+
+impl Display for Origin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Origin::File(path) => write!(f, "{}", path.display()),
+            Origin::Env(name) => write!(f, "${name}"),
+            Origin::CommandLine => write!(f, "command line"),
+        }
+    }
+}
+
+/// Config merged from several layers, later layers override single keys of earlier ones.
+/// Tables are merged key by key, all other values including arrays are replaced.
+#[derive(Debug, Default)]
+pub struct LayeredConfig {
+    table: Table,
+    origins: BTreeMap<String, Origin>,
+}
+
+impl LayeredConfig {
+    /// Merges a config file.
+    pub fn merge_file(&mut self, path: PathBuf, raw: &str) -> Result<(), ConfigError> {
+        let layer = toml::from_str(raw)
+            .map_err(|e| ConfigError::ParseFile(path.display().to_string(), e))?;
+        self.merge(layer, &Origin::File(path));
+        Ok(())
+    }
+
+    /// Merges all `CARBOND_*` variables, nested keys are separated by `__`.
+    /// Values are taken as string, so `30m` needs no quotes and numeric secrets stay strings.
+    /// Values of keys in [`NON_STRING_KEYS`] are parsed as toml instead.
+    pub fn merge_env(&mut self, vars: impl IntoIterator<Item = (String, String)>) {
+        for (name, raw) in vars {
+            let Some(key) = name.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let path: Vec<String> = key
+                .to_lowercase()
+                .split(ENV_SEPARATOR)
+                .map(str::to_owned)
+                .collect();
+            if path.iter().any(String::is_empty) {
+                continue;
+            }
+            let value = if is_non_string(&path) {
+                parse_env_value(&raw)
+            } else {
+                Value::String(raw)
+            };
+            let layer = path.into_iter().rev().fold(value, |value, key| {
+                Value::Table(Table::from_iter([(key, value)]))
+            });
+            if let Value::Table(layer) = layer {
+                self.merge(layer, &Origin::Env(name));
+            }
+        }
+    }
+
+    /// Merges a single value, e.g. from the command line.
+    pub fn set(&mut self, key: &str, value: Value, origin: Origin) {
+        self.merge(Table::from_iter([(key.to_owned(), value)]), &origin);
+    }
+
+    pub fn merge(&mut self, layer: Table, origin: &Origin) {
+        merge_table(&mut self.table, layer, "", origin, &mut self.origins);
+    }
+
+    pub fn table(&self) -> &Table {
+        &self.table
+    }
+
+    /// Lists every value with its dotted key and the layer it was set in.
+    /// Secrets are redacted.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        render_table(&self.table, "", &self.origins, &mut out);
+        out
+    }
+}
+
+fn merge_table(
+    target: &mut Table,
+    layer: Table,
+    prefix: &str,
+    origin: &Origin,
+    origins: &mut BTreeMap<String, Origin>,
+) {
+    for (key, value) in layer {
+        let path = format!("{prefix}{key}");
+        match (target.get_mut(&key), value) {
+            (Some(Value::Table(target)), Value::Table(layer)) => {
+                merge_table(target, layer, &format!("{path}."), origin, origins);
+            }
+            (_, value) => {
+                let nested = format!("{path}.");
+                origins.retain(|key, _| key != &path && !key.starts_with(&nested));
+                match value {
+                    Value::Table(layer) => {
+                        let mut table = Table::new();
+                        merge_table(&mut table, layer, &nested, origin, origins);
+                        target.insert(key, Value::Table(table));
+                    }
+                    value => {
+                        origins.insert(path, origin.clone());
+                        target.insert(key, value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Whether a key is listed in [`NON_STRING_KEYS`].
+fn is_non_string(path: &[String]) -> bool {
+    NON_STRING_KEYS.iter().any(|key| {
+        let pattern: Vec<&str> = key.split('.').collect();
+        pattern.len() == path.len()
+            && pattern
+                .iter()
+                .zip(path)
+                .all(|(pattern, key)| *pattern == "*" || pattern == key)
+    })
+}
+
+/// Parses a value as toml, taking it as string if that fails.
+fn parse_env_value(raw: &str) -> Value {
+    format!("value = {raw}")
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut table| table.remove("value"))
+        .unwrap_or_else(|| Value::String(raw.to_owned()))
+}
+
+fn render_table(table: &Table, prefix: &str, origins: &BTreeMap<String, Origin>, out: &mut String) {
+    for (key, value) in table {
+        let path = format!("{prefix}{}", render_key(key));
+        match value {
+            Value::Table(table) => render_table(table, &format!("{path}."), origins, out),
+            value => {
+                let value = match value {
+                    Value::String(_) if SECRET_KEYS.contains(&key.as_str()) => {
+                        Value::String("[redacted]".to_owned())
+                    }
+                    value => value.clone(),
+                };
+                let origin = origins
+                    .get(&format!("{prefix}{key}"))
+                    .map_or_else(String::new, |origin| format!(" # {origin}"));
+                out.push_str(&format!("{path} = {value}{origin}\n"));
+            }
+        }
+    }
+}
+
+/// Quotes keys that are not allowed as bare keys.
+fn render_key(key: &str) -> String {
+    let bare = !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    if bare {
+        key.to_owned()
+    } else {
+        Value::String(key.to_owned()).to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn layered(files: &[(&str, &str)]) -> LayeredConfig {
+        let mut config = LayeredConfig::default();
+        for (path, raw) in files {
+            config.merge_file(PathBuf::from(path), raw).unwrap();
+        }
+        config
+    }
+
+    #[test]
+    fn test_merge_files() {
+        let config = layered(&[
+            (
+                "config.toml",
+                "update_interval = \"1h\"\n[intensity_service.watt_time]\nregion = \"A\"\nusername = \"u\"\n",
+            ),
+            (
+                "conf.d/10-host.toml",
+                "[intensity_service.watt_time]\nregion = \"B\"\n",
+            ),
+        ]);
+
+        let table = config.table();
+        assert_eq!(table["update_interval"].as_str(), Some("1h"));
+        let watt_time = &table["intensity_service"]["watt_time"];
+        assert_eq!(watt_time["region"].as_str(), Some("B"));
+        assert_eq!(watt_time["username"].as_str(), Some("u"));
+    }
+
+    #[test]
+    fn test_merge_env() {
+        let mut config = layered(&[("config.toml", "update_interval = \"1h\"\n")]);
+
+        config.merge_env([
+            ("CARBOND_UPDATE_INTERVAL".to_owned(), "30m".to_owned()),
+            ("CARBOND_LOGGING_VERBOSITY".to_owned(), "2".to_owned()),
+            (
+                "CARBOND_INTENSITY_SERVICE__PRIORITY".to_owned(),
+                "[\"watt_time\"]".to_owned(),
+            ),
+            ("HOME".to_owned(), "/root".to_owned()),
+        ]);
+
+        let table = config.table();
+        assert_eq!(table["update_interval"].as_str(), Some("30m"));
+        assert_eq!(table["logging_verbosity"].as_integer(), Some(2));
+        assert_eq!(
+            table["intensity_service"]["priority"],
+            Value::Array(vec![Value::String("watt_time".to_owned())])
+        );
+        assert!(!table.contains_key("home"));
+    }
+
+    #[test]
+    fn test_merge_env_keeps_strings() {
+        let mut config = LayeredConfig::default();
+
+        config.merge_env([
+            (
+                "CARBOND_INTENSITY_SERVICE__WATT_TIME__PASSWORD".to_owned(),
+                "123456".to_owned(),
+            ),
+            (
+                "CARBOND_INTENSITY_SERVICE__ELECTRICITY_MAP__REGION".to_owned(),
+                "true".to_owned(),
+            ),
+            (
+                "CARBOND_JOBS__FORECAST__ALIGN".to_owned(),
+                "true".to_owned(),
+            ),
+            (
+                "CARBOND_JOBS__HISTORY__MAX_RETRIES".to_owned(),
+                "5".to_owned(),
+            ),
+        ]);
+
+        let table = config.table();
+        let service = &table["intensity_service"];
+        assert_eq!(service["watt_time"]["password"].as_str(), Some("123456"));
+        assert_eq!(service["electricity_map"]["region"].as_str(), Some("true"));
+        assert_eq!(table["jobs"]["forecast"]["align"].as_bool(), Some(true));
+        assert_eq!(
+            table["jobs"]["history"]["max_retries"].as_integer(),
+            Some(5)
+        );
+    }
+
+    #[test]
+    fn test_render_origins() {
+        let mut config = layered(&[
+            (
+                "config.toml",
+                "update_interval = \"1h\"\n[intensity_service.watt_time]\nregion = \"A\"\npassword = \"p\"\n",
+            ),
+            ("conf.d/10-host.toml", "update_interval = \"2h\"\n"),
+        ]);
+        config.merge_env([(
+            "CARBOND_INTENSITY_SERVICE__WATT_TIME__REGION".to_owned(),
+            "B".to_owned(),
+        )]);
+        config.set(
+            "data_root",
+            Value::String("/tmp/carbond".to_owned()),
+            Origin::CommandLine,
+        );
+
+        assert_eq!(
+            config.render(),
+            "data_root = \"/tmp/carbond\" # command line\n\
+             intensity_service.watt_time.password = \"[redacted]\" # config.toml\n\
+             intensity_service.watt_time.region = \"B\" # $CARBOND_INTENSITY_SERVICE__WATT_TIME__REGION\n\
+             update_interval = \"2h\" # conf.d/10-host.toml\n"
+        );
+    }
+
+    #[test]
+    fn test_replaced_table_drops_origins() {
+        let mut config = layered(&[("config.toml", "[device.cpu]\nembodied_g = 1.0\n")]);
+
+        config.set(
+            "device",
+            Value::String("none".to_owned()),
+            Origin::CommandLine,
+        );
+
+        assert_eq!(config.render(), "device = \"none\" # command line\n");
+    }
+}
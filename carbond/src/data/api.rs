@@ -2,12 +2,27 @@
 
 use serde::Deserialize;
 
+/// Response of the WattTime v3 `signal-index`, `forecast` and `historical` endpoints.
 #[derive(Deserialize, Debug)]
-pub(crate) struct WattTimeResponse {
-    ba: String,
-    freq: String,
-    pub moer: String,
-    point_time: String,
+pub(crate) struct WattTimeSignalResponse {
+    pub data: Vec<WattTimeDataPoint>,
+    pub meta: WattTimeMeta,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct WattTimeDataPoint {
+    pub point_time: String,
+    pub value: f64,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct WattTimeMeta {
+    pub region: String,
+    pub signal_type: String,
+    pub units: String,
+    pub data_point_period_seconds: Option<i64>,
+    /// Only given for forecasts.
+    pub generated_at: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -17,6 +32,92 @@ pub(crate) struct WattTimeLoginResponse {
 
 #[derive(Deserialize, Debug)]
 pub(crate) struct WattTimeError {
-    pub error: String,
+    pub error: Option<String>,
+    pub message: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ElectricityMapResponse {
+    pub zone: String,
+    pub carbon_intensity: Option<f64>,
+    pub datetime: String,
+    updated_at: String,
+    emission_factor_type: Option<String>,
+    is_estimated: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct ElectricityMapError {
+    pub message: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ElectricityMapForecastResponse {
+    pub zone: String,
+    pub forecast: Vec<ElectricityMapForecastPoint>,
+    pub updated_at: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub(crate) struct ElectricityMapForecastPoint {
+    pub carbon_intensity: f64,
+    pub datetime: String,
+}
+
+/// Response of the regional endpoints of the National Grid Carbon Intensity API.
+#[derive(Deserialize, Debug)]
+pub(crate) struct NationalGridResponse {
+    pub data: NationalGridRegions,
+}
+
+/// The current intensity is given as list of regions, forecasts for a single region.
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+pub(crate) enum NationalGridRegions {
+    Many(Vec<NationalGridRegion>),
+    One(NationalGridRegion),
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct NationalGridRegion {
+    pub regionid: u8,
+    pub shortname: String,
+    pub dnoregion: Option<String>,
+    pub postcode: Option<String>,
+    pub data: Vec<NationalGridPeriod>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct NationalGridPeriod {
+    pub from: String,
+    pub to: String,
+    pub intensity: NationalGridIntensity,
+    #[serde(default)]
+    pub generationmix: Vec<NationalGridFuel>,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct NationalGridIntensity {
+    pub forecast: Option<f64>,
+    pub index: String,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct NationalGridFuel {
+    pub fuel: String,
+    pub perc: f64,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct NationalGridError {
+    pub error: NationalGridErrorDetail,
+}
+
+#[derive(Deserialize, Debug)]
+pub(crate) struct NationalGridErrorDetail {
+    pub code: String,
     pub message: String,
 }
@@ -4,27 +4,85 @@ use serde::{Deserialize, Serialize};
 pub(crate) struct ConfigRepr {
     pub logging_verbosity: Option<usize>,
     pub update_interval: String,
+    pub forecast_interval: Option<String>,
+    pub data_root: Option<String>,
+    pub shutdown_timeout: Option<String>,
+    pub secrets_file: Option<String>,
+    pub user: Option<String>,
+    pub group: Option<String>,
+    pub hardening: Option<HardeningRepr>,
+    pub http: Option<HttpRepr>,
     pub intensity_service: IntensityServiceRepr,
     pub device: Option<DeviceConfigRepr>,
+    pub jobs: Option<JobsRepr>,
+    pub history: Option<HistoryRepr>,
 }
 
 #[derive(Deserialize, Serialize)]
 pub(crate) struct IntensityServiceRepr {
+    pub priority: Option<Vec<ProviderRepr>>,
     pub electricity_map: Option<ElectricityMapRepr>,
     pub watt_time: Option<WattTimeRepr>,
+    pub national_grid: Option<NationalGridRepr>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ProviderRepr {
+    WattTime,
+    ElectricityMap,
+    NationalGrid,
 }
 
 #[derive(Deserialize, Serialize)]
 pub(crate) struct ElectricityMapRepr {
     pub region: String,
-    pub token: String,
+    pub base_url: Option<String>,
+    pub token: Option<SecretRepr>,
 }
 
 #[derive(Deserialize, Serialize)]
 pub(crate) struct WattTimeRepr {
     pub region: String,
-    pub username: String,
-    pub password: String,
+    pub base_url: Option<String>,
+    pub signal_type: Option<SignalTypeRepr>,
+    pub username: Option<SecretRepr>,
+    pub password: Option<SecretRepr>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SignalTypeRepr {
+    Co2Moer,
+    Co2Aoer,
+    HealthDamage,
+}
+
+/// Region of the National Grid Carbon Intensity API, either a postcode or a region id.
+#[derive(Deserialize, Serialize)]
+pub(crate) struct NationalGridRepr {
+    pub postcode: Option<String>,
+    pub region_id: Option<u8>,
+    pub base_url: Option<String>,
+}
+
+/// Secret given in plaintext, as path of a file holding it or as name of an environment variable.
+#[derive(Deserialize, Serialize, Clone)]
+#[serde(untagged)]
+pub(crate) enum SecretRepr {
+    Plain(String),
+    File { file: String },
+    Env { env: String },
+}
+
+#[derive(Deserialize, Serialize, Default)]
+pub(crate) struct HttpRepr {
+    pub connect_timeout: Option<String>,
+    pub request_timeout: Option<String>,
+    pub proxy: Option<String>,
+    pub no_proxy: Option<Vec<String>>,
+    pub ca_certificates: Option<Vec<String>>,
+    pub user_agent: Option<String>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -37,3 +95,44 @@ pub(crate) struct CpuConfigRepr {
     pub embodied_g: f64,
     pub lifetime_cycles: u64,
 }
+
+#[derive(Deserialize, Serialize, Default)]
+pub(crate) struct JobsRepr {
+    pub carbon_intensity: Option<JobRepr>,
+    pub forecast: Option<JobRepr>,
+    pub history: Option<JobRepr>,
+}
+
+#[derive(Deserialize, Serialize, Default)]
+pub(crate) struct JobRepr {
+    pub schedule: Option<String>,
+    pub align: Option<bool>,
+    pub on_error: Option<ErrorPolicyRepr>,
+    pub max_retries: Option<u32>,
+    pub retry_delay: Option<String>,
+    pub max_retry_delay: Option<String>,
+    pub max_staleness: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum HardeningRepr {
+    Off,
+    BestEffort,
+    Required,
+}
+
+#[derive(Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ErrorPolicyRepr {
+    Retry,
+    Skip,
+    Fatal,
+}
+
+#[derive(Deserialize, Serialize, Default)]
+pub(crate) struct HistoryRepr {
+    pub raw_retention: Option<String>,
+    pub hourly_retention: Option<String>,
+    pub daily_retention: Option<String>,
+}
@@ -1,27 +1,127 @@
-use std::time::Duration;
+use std::{path::PathBuf, time::Duration};
 
+use carbond_lib::history::Retention;
 use uom::si::f64::Mass;
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub logging_verbosity: usize,
     pub update_interval: Duration,
+    /// Interval of forecast updates, forecasts are not requested if missing.
+    pub forecast_interval: Option<Duration>,
+    /// Directory the metrics are published in.
+    pub data_root: PathBuf,
+    /// Time running jobs may take to finish on shutdown.
+    pub shutdown_timeout: Duration,
+    /// User carbond switches to after setting up its directories.
+    pub user: Option<String>,
+    /// Group carbond switches to, the primary group of `user` if missing.
+    pub group: Option<String>,
+    /// Secrets file that has to stay readable for reloads.
+    pub secrets_file: Option<PathBuf>,
+    /// Files of single secrets given as `{ file = "..." }`, which have to stay readable for reloads.
+    pub secret_files: Vec<PathBuf>,
+    pub hardening: Hardening,
+    pub http: HttpConfig,
+    pub provider_priority: Vec<ProviderKind>,
     pub electricity_map: Option<ElectricityMap>,
     pub watt_time: Option<WattTime>,
+    pub national_grid: Option<NationalGrid>,
     pub device_config: Option<DeviceConfig>,
+    pub jobs: JobsConfig,
+    pub history: Retention,
+}
+
+/// Sandboxing of the carbond process with Landlock and seccomp.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Hardening {
+    #[default]
+    Off,
+    /// Applies the sandbox as far as the kernel supports it.
+    BestEffort,
+    /// Fails if the kernel cannot enforce the full sandbox.
+    Required,
+}
+
+/// HTTP client shared by all providers, so connections are reused between requests.
+#[derive(Debug, Clone)]
+pub struct HttpConfig {
+    pub client: reqwest::Client,
+    /// Extra root certificates that have to stay readable for reloads.
+    pub ca_certificates: Vec<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ProviderKind {
+    WattTime,
+    ElectricityMap,
+    NationalGrid,
 }
 
 #[derive(Debug, Clone)]
 pub struct ElectricityMap {
     pub region: String,
-    pub token: String,
+    /// Base URL of the API without trailing slash.
+    pub base_url: String,
+    pub token: Secret,
 }
 
 #[derive(Debug, Clone)]
 pub struct WattTime {
     pub region: String,
-    pub username: String,
-    pub password: String,
+    /// Base URL of the API without trailing slash.
+    pub base_url: String,
+    pub signal_type: SignalType,
+    pub username: Secret,
+    pub password: Secret,
+}
+
+#[derive(Debug, Clone)]
+pub struct NationalGrid {
+    pub region: NationalGridRegion,
+    /// Base URL of the API without trailing slash.
+    pub base_url: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NationalGridRegion {
+    /// Outward code of a UK postcode, e.g. `RG10`.
+    Postcode(String),
+    /// Region id of the API, e.g. `16` for Scotland.
+    RegionId(u8),
+}
+
+/// Signal requested from WattTime.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SignalType {
+    /// Marginal operating emissions rate of CO2.
+    Co2Moer,
+    /// Average operating emissions rate of CO2.
+    Co2Aoer,
+    /// Marginal health damage, given in the unit reported by WattTime and thus not published
+    /// as carbon intensity.
+    HealthDamage,
+}
+
+/// Credential that is redacted when debug printed, so it does not end up in logs.
+#[derive(Clone, PartialEq)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(secret: String) -> Self {
+        Secret(secret)
+    }
+
+    /// Returns the plaintext secret to send it to a provider.
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl std::fmt::Debug for Secret {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Secret([redacted])")
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -34,3 +134,46 @@ pub struct CpuConfig {
     pub embodied_g: Mass,
     pub lifetime_cycles: u64,
 }
+
+#[derive(Debug, Clone)]
+pub struct JobsConfig {
+    pub carbon_intensity: JobPolicy,
+    pub forecast: JobPolicy,
+    pub history: JobPolicy,
+    /// Follows the carbon intensity job, paused unless WattTime reports the health damage.
+    pub health_damage: JobPolicy,
+}
+
+/// Describes when a job runs and how the scheduler reacts to it failing.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JobPolicy {
+    /// Times the job runs at, the job is paused if missing.
+    pub schedule: Option<Schedule>,
+    pub on_error: ErrorPolicy,
+    /// Time without a successful run after which the job escalates its error.
+    pub max_staleness: Option<Duration>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Schedule {
+    /// Runs every period, aligned ticks fall on multiples of the period since the Unix epoch,
+    /// e.g. on :00 and :05 for five minutes.
+    Every { period: Duration, aligned: bool },
+    /// Runs at the times of a cron expression in UTC.
+    Cron(Box<cron::Schedule>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ErrorPolicy {
+    /// Retries transient errors with exponential backoff and jitter,
+    /// then keeps the last value.
+    Retry {
+        max_retries: u32,
+        base_delay: Duration,
+        max_delay: Duration,
+    },
+    /// Keeps the last value until the next scheduled run.
+    Skip,
+    /// Ends the service on errors that are not transient.
+    Fatal,
+}
@@ -1,19 +1,127 @@
 use carbond_lib::metrics::carbon_intensity::CarbonIntensity;
-use carbond_lib::metrics::metric::Metric;
+use carbond_lib::metrics::metadata::MetricMetadata;
+use carbond_lib::metrics::metric::{Metric, MetricError};
+use chrono::{DateTime, Utc};
+use std::{
+    collections::HashMap,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use crate::api::{watt_time::TokenCache, IntensityKind};
 
 /// State shared accross carbond jobs.
 #[derive(Debug)]
 pub struct State {
-    pub moer: CarbonIntensity,
+    /// Carbon intensity of the highest priority provider.
+    pub intensity: CarbonIntensity,
+    /// Provenance of the current carbon intensity.
+    pub metadata: Option<MetricMetadata>,
+    /// WattTime token reused until it expires.
+    pub watt_time_token: TokenCache,
+    /// Last update of each kind of carbon intensity.
+    updates: HashMap<IntensityKind, IntensityUpdate>,
+    /// Start of the service, kinds never updated are stale since then.
+    started_at: Instant,
+}
+
+/// When a kind of carbon intensity was last updated and until when its value is valid.
+#[derive(Debug, Clone, Copy)]
+struct IntensityUpdate {
+    updated_at: Instant,
+    valid_until: Option<DateTime<Utc>>,
 }
 
 impl State {
-    /// Creates a new instance of carbond state.
-    pub async fn new() -> State {
+    /// Creates a new instance of carbond state from the metrics in the given data root.
+    pub async fn new(data_root: &Path) -> State {
         State {
-            moer: CarbonIntensity::try_read_from_fs()
+            intensity: CarbonIntensity::try_read_from_dir(data_root)
                 .await
                 .unwrap_or(CarbonIntensity::neutral()),
+            metadata: CarbonIntensity::try_read_metadata_from_dir(data_root)
+                .await
+                .ok(),
+            watt_time_token: TokenCache::default(),
+            updates: HashMap::new(),
+            started_at: Instant::now(),
+        }
+    }
+
+    /// Records that a kind of carbon intensity was updated with a value valid until the given time.
+    pub(crate) fn record_update(
+        &mut self,
+        kind: IntensityKind,
+        valid_until: Option<DateTime<Utc>>,
+    ) {
+        let update = IntensityUpdate {
+            updated_at: Instant::now(),
+            valid_until,
+        };
+        self.updates.insert(kind, update);
+    }
+
+    /// Kinds of carbon intensity whose last value is still valid at the given time.
+    pub(crate) fn valid_kinds(&self, now: DateTime<Utc>) -> Vec<IntensityKind> {
+        self.updates
+            .iter()
+            .filter(|(_, update)| update.valid_until.is_some_and(|until| until > now))
+            .map(|(kind, _)| *kind)
+            .collect()
+    }
+
+    /// Time since a kind of carbon intensity was last updated or since the start.
+    pub(crate) fn stale_for(&self, kind: IntensityKind) -> Duration {
+        self.updates
+            .get(&kind)
+            .map_or(self.started_at, |update| update.updated_at)
+            .elapsed()
+    }
+
+    /// Writes the current carbon intensity to the file system.
+    /// Nothing is written before an intensity was observed.
+    pub async fn flush(&self, data_root: &Path) -> Result<(), MetricError> {
+        match &self.metadata {
+            Some(metadata) => {
+                self.intensity
+                    .try_write_to_dir_with_metadata(data_root, metadata)
+                    .await
+            }
+            None => Ok(()),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_valid_kinds() {
+        let mut state = State::new(Path::new("/nonexistent")).await;
+        let now = Utc::now();
+        state.record_update(
+            IntensityKind::Marginal,
+            Some(now - chrono::Duration::minutes(1)),
+        );
+        state.record_update(
+            IntensityKind::Average,
+            Some(now + chrono::Duration::minutes(1)),
+        );
+
+        assert_eq!(state.valid_kinds(now), vec![IntensityKind::Average]);
+
+        state.record_update(IntensityKind::Average, None);
+        assert!(state.valid_kinds(now).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stale_for() {
+        let mut state = State::new(Path::new("/nonexistent")).await;
+        state.started_at = Instant::now() - Duration::from_millis(100);
+        state.record_update(IntensityKind::Average, None);
+
+        assert!(state.stale_for(IntensityKind::Marginal) >= Duration::from_millis(100));
+        assert!(state.stale_for(IntensityKind::Average) < Duration::from_millis(100));
+    }
+}
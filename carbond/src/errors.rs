@@ -1,5 +1,5 @@
-use carbond_lib::metrics::metric::MetricError;
-use std::io;
+use carbond_lib::{history::HistoryError, metrics::metric::MetricError};
+use std::{io, time::Duration};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -10,15 +10,35 @@ pub enum CarbondError {
     Config(#[from] ConfigError),
     #[error("Error handling metric.")]
     Metric(#[from] MetricError),
+    #[error("Error handling intensity history.")]
+    History(#[from] HistoryError),
     #[error("IO: {msg}: {source}")]
     Io {
         msg: String,
         #[source]
         source: io::Error,
     },
+    #[error("Could not drop privileges: {0}.")]
+    Privileges(String),
+    #[error("Could not apply sandbox: {0}.")]
+    Sandbox(String),
+    #[error("Job {job} did not succeed for {stale_for:?}.")]
+    Stale { job: String, stale_for: Duration },
+    #[error("Job panicked: {0}.")]
+    Panicked(String),
 }
 
-#[derive(Error, Debug, PartialEq)]
+impl CarbondError {
+    /// Whether the error is caused by a temporary condition, e.g. a network failure.
+    pub fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            CarbondError::Api(APIError::Unhandled(_) | APIError::Timeout(_))
+        )
+    }
+}
+
+#[derive(Error, Debug, Clone, PartialEq)]
 pub enum APIError {
     #[error("Invalid credentials.")]
     InvalidCredentials,
@@ -30,14 +50,46 @@ pub enum APIError {
     Deserialze(String),
     #[error("Something went wrong while getting response from {0}.")]
     Unhandled(String),
+    #[error("Request timed out: {0}.")]
+    Timeout(String),
+}
+
+impl APIError {
+    /// Whether the next intensity provider should be tried after this error.
+    pub fn allows_fallback(&self) -> bool {
+        matches!(
+            self,
+            APIError::Unhandled(_) | APIError::InvalidCredentials | APIError::Timeout(_)
+        )
+    }
 }
 
 #[derive(Error, Debug, PartialEq)]
 pub enum ConfigError {
     #[error("Could not parse config.")]
     ParseConfig(#[from] toml::de::Error),
+    #[error("Could not parse config file {0}: {1}")]
+    ParseFile(String, toml::de::Error),
     #[error("\"{0}\" is not a valid interval.")]
     ParseInterval(String),
+    #[error("\"{0}\" is neither an interval nor a cron expression.")]
+    ParseSchedule(String),
     #[error("Could not find all required options: {0}.")]
     ConfigMissing(String),
+    #[error("Could not read secret {0}: {1}.")]
+    ReadSecret(String, String),
+    #[error("Secrets file {0} must only be accessible by its owner, either root or carbond.")]
+    InsecureSecrets(String),
+    #[error("Config file {0} must not be writable by group or others.")]
+    InsecureConfig(String),
+    #[error("Only one of {0} may be set.")]
+    ConflictingOptions(String),
+    #[error("\"{0}\" is not a valid URL.")]
+    InvalidUrl(String),
+    #[error("\"{0}\" is not a valid region of {1}.")]
+    InvalidRegion(String, &'static str),
+    #[error("Could not read CA certificate {0}: {1}.")]
+    ReadCertificate(String, String),
+    #[error("Could not create HTTP client: {0}.")]
+    HttpClient(String),
 }
@@ -1,36 +1,94 @@
-use carbond_lib::fs::create_file;
+use carbond_lib::{constants, fs::create_file};
 
 use crate::{
-    data::config::external::{ConfigRepr, ElectricityMapRepr, IntensityServiceRepr, WattTimeRepr},
-    errors::CarbondError,
+    data::config::external::{
+        ConfigRepr, ElectricityMapRepr, ErrorPolicyRepr, HistoryRepr, IntensityServiceRepr,
+        JobRepr, JobsRepr, ProviderRepr, SecretRepr, SignalTypeRepr, WattTimeRepr,
+    },
+    errors::{CarbondError, ConfigError},
 };
-use std::{io, path::Path};
+use std::{
+    fs::Permissions,
+    io,
+    os::unix::fs::{MetadataExt, PermissionsExt},
+    path::{Path, PathBuf},
+};
+
+pub(crate) const CONFIG_PATH: &str = "/etc/carbond/config.toml";
 
-const CONFIG_PATH: &str = "/etc/carbond/config.toml";
+/// Mode of the sample config, which may hold credentials once filled in.
+const CONFIG_MODE: u32 = 0o600;
 
 /// Loads the config as a string from the file system.
-pub(super) async fn load_config() -> Result<String, CarbondError> {
-    let file_path = Path::new(CONFIG_PATH);
-    let text = tokio::fs::read_to_string(file_path)
+/// Refuses configs that can be modified by others than their owner.
+pub(super) async fn load_config(config_file: &Path) -> Result<String, CarbondError> {
+    let metadata = tokio::fs::metadata(config_file)
         .await
         .map_err(|err| CarbondError::Io {
-            msg: format!("Could not load config file on path {CONFIG_PATH}"),
+            msg: format!(
+                "Could not load config file on path {}",
+                config_file.display()
+            ),
+            source: err,
+        })?;
+    if metadata.mode() & 0o022 != 0 {
+        return Err(ConfigError::InsecureConfig(config_file.display().to_string()).into());
+    }
+    let text = tokio::fs::read_to_string(config_file)
+        .await
+        .map_err(|err| CarbondError::Io {
+            msg: format!(
+                "Could not load config file on path {}",
+                config_file.display()
+            ),
             source: err,
         })?;
     Ok(text)
 }
 
-/// Validates the file structure of carbond.
-pub(super) async fn validate_file_structure() -> Result<(), CarbondError> {
-    validate_config().await.map_err(|err| CarbondError::Io {
-        msg: format!("Could not validate config file structure on path {CONFIG_PATH}"),
+/// Directory of config fragments, e.g. `/etc/carbond/conf.d`.
+pub(crate) fn fragments_dir(config_file: &Path) -> PathBuf {
+    config_file.with_file_name("conf.d")
+}
+
+/// Lists the `*.toml` fragments next to the config file in lexical order.
+pub(super) async fn config_fragments(config_file: &Path) -> Result<Vec<PathBuf>, CarbondError> {
+    let dir = fragments_dir(config_file);
+    let io_error = |err| CarbondError::Io {
+        msg: format!("Could not list config fragments in {}", dir.display()),
         source: err,
-    })?;
+    };
+    let mut entries = match tokio::fs::read_dir(&dir).await {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(io_error(err)),
+    };
+    let mut fragments = vec![];
+    while let Some(entry) = entries.next_entry().await.map_err(io_error)? {
+        let path = entry.path();
+        if path.extension().is_some_and(|ext| ext == "toml") && path.is_file() {
+            fragments.push(path);
+        }
+    }
+    fragments.sort();
+    Ok(fragments)
+}
+
+/// Validates the file structure of carbond.
+pub(super) async fn validate_file_structure(config_file: &Path) -> Result<(), CarbondError> {
+    validate_config(config_file)
+        .await
+        .map_err(|err| CarbondError::Io {
+            msg: format!(
+                "Could not validate config file structure on path {}",
+                config_file.display()
+            ),
+            source: err,
+        })?;
     Ok(())
 }
 
-async fn validate_config() -> io::Result<()> {
-    let config_file = Path::new(CONFIG_PATH);
+async fn validate_config(config_file: &Path) -> io::Result<()> {
     let file_was_created = create_file(config_file).await?;
     if file_was_created {
         write_sample_config(config_file).await?;
@@ -39,23 +97,61 @@ async fn validate_config() -> io::Result<()> {
 }
 
 async fn write_sample_config(config_file: &Path) -> io::Result<()> {
+    tokio::fs::write(config_file, sample_config()?).await?;
+    tokio::fs::set_permissions(config_file, Permissions::from_mode(CONFIG_MODE)).await?;
+    Ok(())
+}
+
+/// Creates the sample config written on first start.
+pub(super) fn sample_config() -> io::Result<String> {
     let sample_data = ConfigRepr {
         logging_verbosity: Some(0),
         update_interval: "1h".to_owned(),
+        forecast_interval: None,
+        data_root: Some(constants::DATA_PATH.to_owned()),
+        shutdown_timeout: Some("30s".to_owned()),
+        secrets_file: None,
+        user: None,
+        group: None,
+        hardening: None,
+        http: None,
         intensity_service: {
             IntensityServiceRepr {
+                priority: Some(vec![ProviderRepr::WattTime, ProviderRepr::ElectricityMap]),
                 electricity_map: Some(ElectricityMapRepr {
                     region: "".to_owned(),
-                    token: "".to_owned(),
+                    base_url: None,
+                    token: Some(SecretRepr::Plain("".to_owned())),
                 }),
                 watt_time: Some(WattTimeRepr {
                     region: "".to_owned(),
-                    username: "".to_owned(),
-                    password: "".to_owned(),
+                    base_url: None,
+                    signal_type: Some(SignalTypeRepr::Co2Moer),
+                    username: Some(SecretRepr::Plain("".to_owned())),
+                    password: Some(SecretRepr::Plain("".to_owned())),
                 }),
+                national_grid: None,
             }
         },
         device: None,
+        jobs: Some(JobsRepr {
+            carbon_intensity: Some(JobRepr {
+                schedule: None,
+                align: Some(false),
+                on_error: Some(ErrorPolicyRepr::Retry),
+                max_retries: Some(3),
+                retry_delay: Some("10s".to_owned()),
+                max_retry_delay: Some("5m".to_owned()),
+                max_staleness: None,
+            }),
+            forecast: None,
+            history: None,
+        }),
+        history: Some(HistoryRepr {
+            raw_retention: Some("168h".to_owned()),
+            hourly_retention: Some("2160h".to_owned()),
+            daily_retention: None,
+        }),
     };
     let config_string: String = toml::to_string(&sample_data).map_err(|_op| {
         io::Error::new(
@@ -63,6 +159,74 @@ async fn write_sample_config(config_file: &Path) -> io::Result<()> {
             "Could not convert content to toml and write to file.",
         )
     })?;
-    tokio::fs::write(config_file, &config_string).await?;
-    Ok(())
+    Ok(config_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn config_path(dir: &TempDir) -> PathBuf {
+        dir.path().join("carbond").join("config.toml")
+    }
+
+    #[tokio::test]
+    async fn test_sample_config_mode() {
+        let dir = TempDir::new().unwrap();
+        let path = config_path(&dir);
+        validate_file_structure(&path).await.unwrap();
+
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode, CONFIG_MODE);
+        assert!(load_config(&path).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_config_fragments_in_lexical_order() {
+        let root = TempDir::new().unwrap();
+        let path = config_path(&root);
+        let dir = fragments_dir(&path);
+        std::fs::create_dir_all(&dir).unwrap();
+        for name in [
+            "20-host.toml",
+            "10-base.toml",
+            "README",
+            "30-site.toml.orig",
+        ] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+
+        let fragments = config_fragments(&path).await.unwrap();
+
+        assert_eq!(
+            fragments,
+            vec![dir.join("10-base.toml"), dir.join("20-host.toml")]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_config_fragments_missing_dir() {
+        let dir = TempDir::new().unwrap();
+
+        assert!(config_fragments(&config_path(&dir))
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_load_config_refuses_writable_config() {
+        let dir = TempDir::new().unwrap();
+        let path = config_path(&dir);
+        validate_file_structure(&path).await.unwrap();
+
+        for mode in [0o620, 0o602] {
+            std::fs::set_permissions(&path, Permissions::from_mode(mode)).unwrap();
+            assert!(matches!(
+                load_config(&path).await,
+                Err(CarbondError::Config(ConfigError::InsecureConfig(_)))
+            ));
+        }
+    }
 }
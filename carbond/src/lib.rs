@@ -1,54 +1,338 @@
-use std::sync::Arc;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
+use carbond_lib::history::{HistoryStore, IntensityRecord};
 use carbond_lib::metrics::{
-    carbon_intensity::CarbonIntensity, cpu_cycles::CpuCycleIntensity, metric::Metric,
+    carbon_intensity::{AverageCarbonIntensity, CarbonIntensity, MarginalCarbonIntensity},
+    cpu_cycles::CpuCycleIntensity,
+    forecast::CarbonIntensityForecast,
+    health_damage::HealthDamage,
+    metadata::MetricMetadata,
+    metric::Metric,
 };
+use chrono::Utc;
+use config::layers::{LayeredConfig, Origin};
 use data::{
     config::internal::{Config, CpuConfig},
     state::State,
 };
-use errors::CarbondError;
-use log::debug;
+use errors::{APIError, CarbondError};
+use log::{debug, warn};
 
 use tokio::sync::Mutex;
-use uom::si::f64::{Mass, MassPerEnergy};
+use uom::si::f64::Mass;
 
 mod api;
+pub mod cli;
 mod config;
 mod data;
 pub mod errors;
 mod fs;
+pub mod privileges;
+pub mod sandbox;
 pub mod scheduler;
+pub mod systemd;
 
 /// Loads the configuration.
-/// - Validates the file structure.
-/// - Loads the config from the file system.
-pub async fn load_config() -> Result<Config, CarbondError> {
-    fs::validate_file_structure().await?;
-    let config = fs::load_config().await?;
-    let config = Config::try_parse(&config)?;
-    Ok(config)
+/// - Validates the file structure and writes a sample config if the file is missing.
+/// - Merges the config file, its `conf.d` fragments and `CARBOND_*` environment variables.
+pub async fn load_config(path: &Path) -> Result<Config, CarbondError> {
+    ConfigSource::new(path).load().await
+}
+
+/// Checks the configuration without writing a sample config if the file is missing.
+pub async fn check_config(path: &Path) -> Result<Config, CarbondError> {
+    ConfigSource::new(path).load_existing().await
+}
+
+/// Location of the configuration and overrides applied on every load,
+/// e.g. from the command line.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigSource {
+    pub path: PathBuf,
+    pub data_root: Option<PathBuf>,
+    pub logging_verbosity: Option<usize>,
+}
+
+impl ConfigSource {
+    fn new(path: &Path) -> Self {
+        ConfigSource {
+            path: path.to_path_buf(),
+            ..Default::default()
+        }
+    }
+
+    /// Loads the configuration like [`load_config`] and applies the overrides.
+    pub async fn load(&self) -> Result<Config, CarbondError> {
+        fs::validate_file_structure(&self.path).await?;
+        let layers = self.layers().await?;
+        Ok(Config::try_from_table(layers.table().clone())?)
+    }
+
+    /// Loads the configuration like [`check_config`] and applies the overrides.
+    pub async fn load_existing(&self) -> Result<Config, CarbondError> {
+        self.ensure_exists()?;
+        let layers = self.layers().await?;
+        Ok(Config::try_from_table(layers.table().clone())?)
+    }
+
+    /// Lists every configured value with the file, environment variable or
+    /// command line option it was set by. Options not listed use their defaults.
+    pub async fn effective_config(&self) -> Result<String, CarbondError> {
+        self.ensure_exists()?;
+        let layers = self.layers().await?;
+        Config::try_from_table(layers.table().clone())?;
+        Ok(layers.render())
+    }
+
+    /// Merges the config file, its fragments in lexical order,
+    /// the environment and the command line overrides.
+    async fn layers(&self) -> Result<LayeredConfig, CarbondError> {
+        let mut layers = LayeredConfig::default();
+        let raw = fs::load_config(&self.path).await?;
+        layers.merge_file(self.path.clone(), &raw)?;
+        for fragment in fs::config_fragments(&self.path).await? {
+            let raw = fs::load_config(&fragment).await?;
+            layers.merge_file(fragment, &raw)?;
+        }
+        layers.merge_env(std::env::vars_os().filter_map(|(name, value)| {
+            Some((name.into_string().ok()?, value.into_string().ok()?))
+        }));
+        if let Some(data_root) = &self.data_root {
+            layers.set(
+                "data_root",
+                toml::Value::String(data_root.display().to_string()),
+                Origin::CommandLine,
+            );
+        }
+        if let Some(logging_verbosity) = self.logging_verbosity {
+            layers.set(
+                "logging_verbosity",
+                toml::Value::Integer(logging_verbosity as i64),
+                Origin::CommandLine,
+            );
+        }
+        Ok(layers)
+    }
+
+    fn ensure_exists(&self) -> Result<(), CarbondError> {
+        if !self.path.is_file() {
+            return Err(CarbondError::Io {
+                msg: format!("Config file {} does not exist", self.path.display()),
+                source: io::Error::from(io::ErrorKind::NotFound),
+            });
+        }
+        Ok(())
+    }
+}
+
+/// Creates the default configuration in toml format.
+pub fn default_config() -> Result<String, CarbondError> {
+    fs::sample_config().map_err(|err| CarbondError::Io {
+        msg: "Could not create default config".to_owned(),
+        source: err,
+    })
 }
 
 /// Loads the current state.
-/// Tries to read from fs or uses default values.
-pub async fn load_state() -> State {
-    data::state::State::new().await
+/// Tries to read from the configured data root or uses default values.
+pub async fn load_state(config: &Config) -> State {
+    data::state::State::new(&config.data_root).await
 }
 
 /// Updates the file system's stored carbon intensity.
-/// - Downloads actual carbon intensity from WattTime.
-/// - Writes carbon intensity to the file system.
+/// - Downloads the marginal and average carbon intensity from the configured providers
+///   in order of priority. Kinds whose last value is still valid are not downloaded again.
+/// - Writes each carbon intensity and its metadata to the file system.
+/// - Writes the carbon intensity of the highest priority provider's kind to the kind-agnostic
+///   path and appends newly observed values of that kind to the history.
+/// - Shows the carbon intensity as status of the service.
+///
+/// A kind that could not be downloaded keeps its last value, which only fails the job once
+/// it is older than the job's maximum staleness. Fails if no kind could be downloaded.
 pub async fn update_carbon_intensity(
     config: Arc<Mutex<Config>>,
     state: Arc<Mutex<State>>,
 ) -> Result<(), CarbondError> {
-    let config = config.lock().await;
+    let config = config.lock().await.clone();
     debug!("Running PowerIntensityUpdate,");
-    let carbon_intensity: CarbonIntensity = download_carbon_intensity(&config).await?;
-    carbon_intensity.try_write_to_fs().await?;
+    let (watt_time_token, valid_kinds) = {
+        let state = state.lock().await;
+        (state.watt_time_token.clone(), state.valid_kinds(Utc::now()))
+    };
+    let providers = api::providers(&config, &watt_time_token)?;
+    let Some(primary_kind) = providers.first().map(|provider| provider.kind()) else {
+        return Err(APIError::Unhandled(String::from("No intensity provider configured")).into());
+    };
+    let results = api::request_carbon_intensities(&providers, &valid_kinds).await;
+    let max_staleness = config.jobs.carbon_intensity.max_staleness;
     let mut state = state.lock().await;
-    state.moer = carbon_intensity;
+    let mut updated = false;
+    let mut error = None;
+    for (kind, result) in results {
+        match result {
+            Ok(provided) => {
+                write_carbon_intensity(kind, &provided, &config.data_root).await?;
+                if kind == primary_kind {
+                    update_primary_intensity(&config, &mut state, &provided).await?;
+                }
+                state.record_update(kind, provided.value.valid_until);
+                updated = true;
+            }
+            Err(e) => {
+                warn!("Could not update the {kind} carbon intensity, keeping the last value: {e}");
+                let stale_for = state.stale_for(kind);
+                if max_staleness.is_some_and(|max| stale_for > max) {
+                    return Err(CarbondError::Stale {
+                        job: format!("PowerIntensityUpdate ({kind})"),
+                        stale_for,
+                    });
+                }
+                error.get_or_insert(e);
+            }
+        }
+    }
+    match error {
+        Some(e) if !updated && valid_kinds.is_empty() => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Writes the carbon intensity of the highest priority provider's kind to the kind-agnostic
+/// path, appends it to the history if it is a new observation and reports it to the
+/// service manager.
+async fn update_primary_intensity(
+    config: &Config,
+    state: &mut State,
+    provided: &api::Provided<api::Observation>,
+) -> Result<(), CarbondError> {
+    let carbon_intensity = CarbonIntensity::from_value(provided.value.intensity);
+    let metadata = provided.metadata();
+    carbon_intensity
+        .try_write_to_dir_with_metadata(&config.data_root, &metadata)
+        .await?;
+    let is_new_observation = state
+        .metadata
+        .as_ref()
+        .map(|previous| previous.observed_at != metadata.observed_at)
+        .unwrap_or(true);
+    if is_new_observation {
+        let update_interval = chrono::Duration::from_std(config.update_interval)
+            .unwrap_or(chrono::Duration::hours(1));
+        let record = IntensityRecord {
+            start: metadata.observed_at,
+            end: metadata
+                .valid_until
+                .unwrap_or(metadata.observed_at + update_interval),
+            intensity: provided.value.intensity,
+        };
+        HistoryStore::in_data_root(&config.data_root)
+            .append(&record)
+            .await?;
+    }
+    let percentile = provided
+        .value
+        .percentile
+        .map(|percentile| format!(", percentile {percentile:.0} of the day"))
+        .unwrap_or_default();
+    let index = provided
+        .value
+        .index
+        .map(|index| format!(", {index}"))
+        .unwrap_or_default();
+    let intensity = carbon_intensity.to_string();
+    systemd::notify(&format!(
+        "STATUS=Carbon intensity {intensity} from {} for {}{index}{percentile}",
+        metadata.provider, provided.value.region
+    ));
+    state.intensity = carbon_intensity;
+    state.metadata = Some(metadata);
+    Ok(())
+}
+
+/// Writes a carbon intensity and its metadata to the path of its kind.
+async fn write_carbon_intensity(
+    kind: api::IntensityKind,
+    provided: &api::Provided<api::Observation>,
+    data_root: &Path,
+) -> Result<(), CarbondError> {
+    let metadata = provided.metadata();
+    match kind {
+        api::IntensityKind::Marginal => {
+            MarginalCarbonIntensity::from_value(provided.value.intensity)
+                .try_write_to_dir_with_metadata(data_root, &metadata)
+                .await?
+        }
+        api::IntensityKind::Average => {
+            AverageCarbonIntensity::from_value(provided.value.intensity)
+                .try_write_to_dir_with_metadata(data_root, &metadata)
+                .await?
+        }
+    }
+    Ok(())
+}
+
+/// Updates the file system's stored health damage.
+/// - Downloads the health damage from WattTime in the unit WattTime reports.
+/// - Writes the health damage and its metadata to its own path, apart from the carbon intensity.
+pub async fn update_health_damage(
+    config: Arc<Mutex<Config>>,
+    state: Arc<Mutex<State>>,
+) -> Result<(), CarbondError> {
+    let config = config.lock().await.clone();
+    debug!("Running HealthDamageUpdate,");
+    let Some(wt) = config.watt_time.as_ref() else {
+        return Err(errors::ConfigError::ConfigMissing(String::from(
+            "A config for watt_time is missing",
+        ))
+        .into());
+    };
+    let watt_time_token = state.lock().await.watt_time_token.clone();
+    let provided = api::watt_time::Api::new(config.http.client.clone(), wt, watt_time_token)
+        .signal_value()
+        .await?;
+    HealthDamage::from_value((provided.value.value, provided.value.unit.clone()))
+        .try_write_to_dir_with_metadata(&config.data_root, &provided.metadata())
+        .await?;
+    Ok(())
+}
+
+/// Updates the file system's stored carbon intensity forecast.
+/// - Downloads the forecast from the configured providers in order of priority.
+/// - Writes the forecast and its metadata to the file system.
+pub async fn update_carbon_intensity_forecast(
+    config: Arc<Mutex<Config>>,
+    state: Arc<Mutex<State>>,
+) -> Result<(), CarbondError> {
+    let config = config.lock().await.clone();
+    debug!("Running ForecastUpdate,");
+    let watt_time_token = state.lock().await.watt_time_token.clone();
+    let providers = api::providers(&config, &watt_time_token)?;
+    let provided = api::request_carbon_intensity_forecast(&providers).await?;
+    let forecast = CarbonIntensityForecast::from_value(provided.value.points.clone());
+    forecast
+        .try_write_to_dir_with_metadata(&config.data_root, &provided.metadata())
+        .await?;
+    Ok(())
+}
+
+/// Compacts the carbon intensity history.
+/// - Downsamples values past their retention to hourly and daily aggregates.
+/// - Drops daily aggregates past their retention.
+pub async fn compact_history(
+    config: Arc<Mutex<Config>>,
+    state: Arc<Mutex<State>>,
+) -> Result<(), CarbondError> {
+    let config = config.lock().await.clone();
+    // appending to the history happens while the state is locked
+    let _state = state.lock().await;
+    debug!("Running HistoryCompaction,");
+    HistoryStore::in_data_root(&config.data_root)
+        .compact(Utc::now(), &config.history)
+        .await?;
     Ok(())
 }
 
@@ -62,7 +346,19 @@ pub async fn update_cpu_intensity(config: &Config) -> Result<(), CarbondError> {
     };
 
     let cpu_intensity = load_cpu_intensity(cpu_config);
-    cpu_intensity.try_write_to_fs().await?;
+    let now = Utc::now();
+    let metadata = MetricMetadata {
+        observed_at: now,
+        fetched_at: now,
+        provider: String::from("config"),
+        region: None,
+        valid_until: None,
+        index: None,
+        generation_mix: vec![],
+    };
+    cpu_intensity
+        .try_write_to_dir_with_metadata(&config.data_root, &metadata)
+        .await?;
 
     Ok(())
 }
@@ -71,18 +367,3 @@ fn load_cpu_intensity(cpu_config: &CpuConfig) -> CpuCycleIntensity {
     let mass_per_cycle: Mass = cpu_config.embodied_g / cpu_config.lifetime_cycles as f64;
     CpuCycleIntensity::from_value(mass_per_cycle)
 }
-
-async fn download_carbon_intensity(config: &Config) -> Result<CarbonIntensity, CarbondError> {
-    let watt_time_config = config.watt_time.as_ref().ok_or(CarbondError::Config(
-        errors::ConfigError::ConfigMissing(String::from("A config for watt_time is missing")),
-    ))?;
-    let api = api::Api::new(
-        &watt_time_config.username,
-        &watt_time_config.password,
-        &watt_time_config.region,
-    );
-    let api = api.login().await?;
-    let intensity: MassPerEnergy = api.get_watt_time_moer().await?;
-    let instance = CarbonIntensity::from_value(intensity);
-    Ok(instance)
-}
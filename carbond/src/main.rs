@@ -1,41 +1,121 @@
-use carbond::{load_config, load_state, update_carbon_intensity};
-use carbond::{scheduler::Scheduler, update_cpu_intensity};
+use carbond::{
+    check_config, cli::Cli, compact_history, default_config, load_state, update_carbon_intensity,
+    update_carbon_intensity_forecast, update_health_damage,
+};
+use carbond::{
+    privileges::drop_privileges,
+    sandbox::harden,
+    scheduler::Scheduler,
+    systemd::{self, JournalLogger},
+    update_cpu_intensity,
+};
+use clap::Parser;
 use color_eyre::Result;
 use log::*;
+use std::sync::Once;
+use tokio::runtime::Builder;
 
-#[tokio::main]
-async fn main() -> Result<()> {
+/// Readiness is reported once, after the first carbon intensity was fetched.
+static READY: Once = Once::new();
+
+fn main() -> Result<()> {
     color_eyre::install()?;
-    let init_config = load_config().await?;
-    stderrlog::new()
-        .verbosity(init_config.logging_verbosity)
-        .timestamp(stderrlog::Timestamp::Second)
-        .show_module_names(true)
-        .init()?;
-
-    info!("Starting service.");
-    let state = load_state().await;
-    debug!("Initial state: {:?}", state);
-
-    // one shot jobs
-    debug!("Executing oneshot jobs.");
-    update_cpu_intensity(&init_config).await?;
-
-    debug!("Scheduling recurrent tasks.");
-    let mut scheduler = Scheduler::new(init_config.clone(), state);
-
-    // Schedule PowerIntensityUpdate job
-    scheduler.schedule_job(
-        String::from("PowerIntensityUpdate"),
-        init_config.update_interval,
-        |cfg, state| async { update_carbon_intensity(cfg, state).await },
-    );
-
-    // wait until ctrl+c or error occurs
-    scheduler.await_service_end().await?;
-
-    info!("Shutting down initialized.");
-    scheduler.shutdown().await;
-    info!("Gracefully shut down successful.");
-    Ok(())
+    let cli = Cli::parse();
+
+    // Landlock only restricts the calling thread and the threads it starts afterwards,
+    // so the service is set up and sandboxed on this thread before the runtime starts its workers.
+    let setup = Builder::new_current_thread().enable_all().build()?;
+    let Some((source, init_config)) = setup.block_on(async {
+        if cli.print_default_config {
+            print!("{}", default_config()?);
+            return Ok(None);
+        }
+        if cli.check_config {
+            check_config(&cli.config).await?;
+            println!("Config {} is valid.", cli.config.display());
+            return Ok(None);
+        }
+
+        let source = cli.config_source();
+        if cli.print_effective_config {
+            print!("{}", source.effective_config().await?);
+            return Ok(None);
+        }
+        let init_config = source.load().await?;
+        if systemd::is_journal_stream() {
+            JournalLogger::new(init_config.logging_verbosity)?.init()?;
+        } else {
+            stderrlog::new()
+                .verbosity(init_config.logging_verbosity)
+                .timestamp(stderrlog::Timestamp::Second)
+                .show_module_names(true)
+                .init()?;
+        }
+
+        info!("Starting service.");
+        drop_privileges(&init_config, &source.path).await?;
+        harden(&init_config, &source.path).await?;
+        Ok::<_, color_eyre::Report>(Some((source, init_config)))
+    })?
+    else {
+        return Ok(());
+    };
+    drop(setup);
+
+    Builder::new_multi_thread()
+        .enable_all()
+        .build()?
+        .block_on(async {
+            let state = load_state(&init_config).await;
+            debug!("Initial state: {:?}", state);
+
+            // one shot jobs
+            debug!("Executing oneshot jobs.");
+            update_cpu_intensity(&init_config).await?;
+
+            debug!("Scheduling recurrent tasks.");
+            let mut scheduler = Scheduler::new(init_config.clone(), state)
+                .oneshot(cli.once)
+                .reload_from(source);
+
+            // Schedule PowerIntensityUpdate job
+            scheduler.schedule_job(
+                String::from("PowerIntensityUpdate"),
+                |cfg| cfg.jobs.carbon_intensity.clone(),
+                |cfg, state| async {
+                    update_carbon_intensity(cfg, state).await?;
+                    READY.call_once(|| systemd::notify("READY=1"));
+                    Ok(())
+                },
+            );
+
+            // Schedule ForecastUpdate job, paused while forecasts are not configured
+            scheduler.schedule_job(
+                String::from("ForecastUpdate"),
+                |cfg| cfg.jobs.forecast.clone(),
+                |cfg, state| async { update_carbon_intensity_forecast(cfg, state).await },
+            );
+
+            // Schedule HealthDamageUpdate job, paused unless WattTime reports the health damage
+            scheduler.schedule_job(
+                String::from("HealthDamageUpdate"),
+                |cfg| cfg.jobs.health_damage.clone(),
+                |cfg, state| async { update_health_damage(cfg, state).await },
+            );
+
+            // Schedule HistoryCompaction job
+            scheduler.schedule_job(
+                String::from("HistoryCompaction"),
+                |cfg| cfg.jobs.history.clone(),
+                |cfg, state| async { compact_history(cfg, state).await },
+            );
+
+            // wait until ctrl+c, a terminate signal or a fatal error occurs, or all jobs ran once
+            scheduler.await_service_end().await?;
+
+            info!("Shutting down initialized.");
+            scheduler.shutdown().await;
+            info!("Gracefully shut down successful.");
+            Ok(())
+        })
 }
@@ -0,0 +1,226 @@
+use std::{fs::Permissions, os::unix::fs::PermissionsExt, path::Path};
+
+use carbond_lib::fs::create_dir;
+use nix::unistd::{self, FchownatFlags, Gid, Group, Uid, User};
+
+use crate::{data::config::internal::Config, errors::CarbondError, fs::fragments_dir};
+
+/// Mode of config and secrets files shared with the group carbond runs as.
+const SHARED_FILE_MODE: u32 = 0o640;
+/// Mode of the fragments directory shared with the group carbond runs as.
+const SHARED_DIR_MODE: u32 = 0o750;
+
+/// Switches to the configured user and group once the data root is set up.
+/// The data root is handed over to the new owner, so metrics can still be published.
+/// The config, its fragments and the secrets files keep their owner but are made readable
+/// by the new group, so they can still be read when the config is reloaded.
+/// Does nothing if neither user nor group are configured.
+pub async fn drop_privileges(config: &Config, config_file: &Path) -> Result<(), CarbondError> {
+    let Some((uid, gid)) = resolve(config.user.as_deref(), config.group.as_deref())? else {
+        return Ok(());
+    };
+    create_dir(&config.data_root)
+        .await
+        .map_err(|err| CarbondError::Io {
+            msg: format!("Could not create data root {}", config.data_root.display()),
+            source: err,
+        })?;
+    chown_tree(&config.data_root, uid, gid)?;
+
+    if unistd::geteuid().is_root() {
+        share_config(config, config_file, gid)?;
+        unistd::setgroups(&[gid]).map_err(|e| privileges_error("setgroups", e))?;
+    }
+    unistd::setgid(gid).map_err(|e| privileges_error("setgid", e))?;
+    unistd::setuid(uid).map_err(|e| privileges_error("setuid", e))?;
+    log::info!("Dropped privileges to uid {} and gid {}.", uid, gid);
+    Ok(())
+}
+
+/// Looks up the ids to switch to before anything is changed.
+/// The group defaults to the primary group of the user, the user to the current one.
+fn resolve(user: Option<&str>, group: Option<&str>) -> Result<Option<(Uid, Gid)>, CarbondError> {
+    if user.is_none() && group.is_none() {
+        return Ok(None);
+    }
+    let user = user
+        .map(|name| {
+            User::from_name(name)
+                .map_err(|e| privileges_error("user lookup", e))?
+                .ok_or_else(|| CarbondError::Privileges(format!("unknown user {name}")))
+        })
+        .transpose()?;
+    let gid = match group {
+        Some(name) => {
+            Group::from_name(name)
+                .map_err(|e| privileges_error("group lookup", e))?
+                .ok_or_else(|| CarbondError::Privileges(format!("unknown group {name}")))?
+                .gid
+        }
+        None => user.as_ref().map_or_else(unistd::getegid, |user| user.gid),
+    };
+    let uid = user.map_or_else(unistd::geteuid, |user| user.uid);
+    Ok(Some((uid, gid)))
+}
+
+/// Changes the owner of a directory and everything below it without following symlinks.
+fn chown_tree(path: &Path, uid: Uid, gid: Gid) -> Result<(), CarbondError> {
+    let io_error = |err| CarbondError::Io {
+        msg: format!("Could not change owner of {}", path.display()),
+        source: err,
+    };
+    unistd::fchownat(
+        None,
+        path,
+        Some(uid),
+        Some(gid),
+        FchownatFlags::NoFollowSymlink,
+    )
+    .map_err(|e| io_error(e.into()))?;
+    if std::fs::symlink_metadata(path).map_err(io_error)?.is_dir() {
+        for entry in std::fs::read_dir(path).map_err(io_error)? {
+            chown_tree(&entry.map_err(io_error)?.path(), uid, gid)?;
+        }
+    }
+    Ok(())
+}
+
+/// Hands the config, its fragments and the secrets files to a group with read access only.
+/// Missing fragments are skipped.
+fn share_config(config: &Config, config_file: &Path, gid: Gid) -> Result<(), CarbondError> {
+    share_with_group(config_file, gid, SHARED_FILE_MODE)?;
+    for secrets_file in config.secrets_file.iter().chain(&config.secret_files) {
+        share_with_group(secrets_file, gid, SHARED_FILE_MODE)?;
+    }
+    let fragments = fragments_dir(config_file);
+    if !fragments.is_dir() {
+        return Ok(());
+    }
+    share_with_group(&fragments, gid, SHARED_DIR_MODE)?;
+    let io_error = |err| CarbondError::Io {
+        msg: format!("Could not list config fragments in {}", fragments.display()),
+        source: err,
+    };
+    for entry in std::fs::read_dir(&fragments).map_err(io_error)? {
+        let path = entry.map_err(io_error)?.path();
+        if path.is_file() {
+            share_with_group(&path, gid, SHARED_FILE_MODE)?;
+        }
+    }
+    Ok(())
+}
+
+/// Changes the group of a file and sets its mode, keeping its owner.
+fn share_with_group(path: &Path, gid: Gid, mode: u32) -> Result<(), CarbondError> {
+    let io_error = |err| CarbondError::Io {
+        msg: format!("Could not share {} with group {}", path.display(), gid),
+        source: err,
+    };
+    unistd::fchownat(None, path, None, Some(gid), FchownatFlags::FollowSymlink)
+        .map_err(|e| io_error(e.into()))?;
+    std::fs::set_permissions(path, Permissions::from_mode(mode)).map_err(io_error)
+}
+
+fn privileges_error(operation: &str, errno: nix::errno::Errno) -> CarbondError {
+    CarbondError::Privileges(format!("{operation} failed with {errno}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{os::unix::fs::MetadataExt, time::Duration};
+
+    use super::*;
+    use crate::ConfigSource;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_resolve_nothing_configured() {
+        assert_eq!(resolve(None, None).unwrap(), None);
+    }
+
+    #[test]
+    fn test_resolve_unknown_user() {
+        assert!(matches!(
+            resolve(Some("carbond-nonexistent-user"), None),
+            Err(CarbondError::Privileges(_))
+        ));
+        assert!(matches!(
+            resolve(None, Some("carbond-nonexistent-group")),
+            Err(CarbondError::Privileges(_))
+        ));
+    }
+
+    #[test]
+    fn test_resolve_primary_group() {
+        let user = User::from_uid(unistd::geteuid()).unwrap().unwrap();
+
+        let (uid, gid) = resolve(Some(&user.name), None).unwrap().unwrap();
+
+        assert_eq!(uid, user.uid);
+        assert_eq!(gid, user.gid);
+    }
+
+    #[test]
+    fn test_chown_tree() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        std::fs::create_dir_all(root.join("operational")).unwrap();
+        std::fs::write(root.join("operational").join("carbon-intensity"), "1 g/kWh").unwrap();
+        let (uid, gid) = (unistd::geteuid(), unistd::getegid());
+
+        chown_tree(root, uid, gid).unwrap();
+
+        let file = std::fs::metadata(root.join("operational").join("carbon-intensity")).unwrap();
+        assert_eq!((file.uid(), file.gid()), (uid.as_raw(), gid.as_raw()));
+    }
+
+    #[tokio::test]
+    async fn test_share_config_for_reload() {
+        let dir = TempDir::new().unwrap();
+        let root = dir.path();
+        let config_file = root.join("config.toml");
+        let secrets_file = root.join("secrets.toml");
+        let fragments = fragments_dir(&config_file);
+        let fragment = fragments.join("10-site.toml");
+        std::fs::create_dir_all(&fragments).unwrap();
+        std::fs::write(
+            &config_file,
+            format!(
+                "update_interval = \"1h\"\nsecrets_file = \"{}\"\n[intensity_service.electricity_map]\nregion = \"FR\"\n",
+                secrets_file.display()
+            ),
+        )
+        .unwrap();
+        std::fs::write(&fragment, "update_interval = \"5m\"\n").unwrap();
+        std::fs::write(&secrets_file, "[electricity_map]\ntoken = \"123\"\n").unwrap();
+        for file in [&config_file, &fragment, &secrets_file] {
+            std::fs::set_permissions(file, Permissions::from_mode(0o600)).unwrap();
+        }
+        let source = ConfigSource {
+            path: config_file.clone(),
+            ..Default::default()
+        };
+        let config = source.load_existing().await.unwrap();
+        let gid = unistd::getegid();
+
+        share_config(&config, &config_file, gid).unwrap();
+
+        for (path, mode) in [
+            (&config_file, SHARED_FILE_MODE),
+            (&fragment, SHARED_FILE_MODE),
+            (&secrets_file, SHARED_FILE_MODE),
+            (&fragments, SHARED_DIR_MODE),
+        ] {
+            let metadata = std::fs::metadata(path).unwrap();
+            assert_eq!(
+                (metadata.mode() & 0o777, metadata.gid()),
+                (mode, gid.as_raw())
+            );
+        }
+        // only secrets owned by root may be readable by their group
+        if unistd::geteuid().is_root() {
+            let reloaded = source.load_existing().await.unwrap();
+            assert_eq!(reloaded.update_interval, Duration::from_secs(5 * 60));
+        }
+    }
+}
@@ -0,0 +1,361 @@
+use std::{collections::BTreeMap, path::Path};
+
+use carbond_lib::fs::create_dir;
+use landlock::{
+    path_beneath_rules, Access, AccessFs, CompatLevel, Compatible, RestrictionStatus, Ruleset,
+    RulesetAttr, RulesetCreatedAttr, RulesetStatus, ABI,
+};
+use seccompiler::{BpfProgram, SeccompAction, SeccompFilter, TargetArch};
+
+use crate::{
+    config::CREDENTIALS_DIRECTORY_ENV,
+    data::config::internal::{Config, Hardening},
+    errors::CarbondError,
+    fs::fragments_dir,
+};
+
+/// System files read by name resolution, TLS and lazily loaded libraries,
+/// and the process' own entry in `/proc` checked for the owner of secrets.
+/// Missing paths are skipped.
+const SYSTEM_PATHS: &[&str] = &[
+    "/proc/self",
+    "/etc/resolv.conf",
+    "/etc/hosts",
+    "/etc/host.conf",
+    "/etc/nsswitch.conf",
+    "/etc/gai.conf",
+    "/etc/ssl",
+    "/etc/pki",
+    "/etc/ca-certificates",
+    "/usr/share/ca-certificates",
+    "/usr/lib/ssl",
+    "/run/systemd/resolve",
+    "/lib",
+    "/lib64",
+    "/usr/lib",
+    "/usr/lib64",
+];
+
+/// Restricts the process to the files and system calls carbond needs.
+/// Landlock limits writes to the data root and reads to the config, secrets and system files
+/// needed for HTTPS, a seccomp filter denies all system calls not used by tokio, reqwest and file I/O.
+/// Has to be called after privileges were dropped, as changing users is denied afterwards.
+/// Landlock only restricts the calling thread and the threads it starts afterwards,
+/// so this has to run on the main thread before the runtime of the service is built.
+pub async fn harden(config: &Config, config_file: &Path) -> Result<(), CarbondError> {
+    let compat_level = match config.hardening {
+        Hardening::Off => return Ok(()),
+        Hardening::BestEffort => CompatLevel::BestEffort,
+        Hardening::Required => CompatLevel::HardRequirement,
+    };
+    // rules only apply to existing paths
+    create_dir(&config.data_root)
+        .await
+        .map_err(|err| CarbondError::Io {
+            msg: format!("Could not create data root {}", config.data_root.display()),
+            source: err,
+        })?;
+
+    let status = restrict_filesystem(config, config_file, compat_level)
+        .map_err(|e| CarbondError::Sandbox(format!("Landlock: {e}")))?;
+    match status.ruleset {
+        RulesetStatus::FullyEnforced => log::info!("Filesystem access restricted with Landlock."),
+        RulesetStatus::PartiallyEnforced => {
+            log::warn!("Filesystem access only partially restricted, Landlock ABI is outdated.")
+        }
+        RulesetStatus::NotEnforced if config.hardening == Hardening::Required => {
+            return Err(CarbondError::Sandbox(
+                "Landlock is not supported by the kernel".to_owned(),
+            ));
+        }
+        RulesetStatus::NotEnforced => {
+            log::warn!("Filesystem access not restricted, Landlock is not supported.")
+        }
+    }
+
+    match restrict_syscalls() {
+        Ok(()) => log::info!("System calls restricted with seccomp."),
+        Err(e) if config.hardening == Hardening::Required => return Err(e),
+        Err(e) => log::warn!("System calls not restricted: {}", e),
+    }
+    Ok(())
+}
+
+fn restrict_filesystem(
+    config: &Config,
+    config_file: &Path,
+    compat_level: CompatLevel,
+) -> Result<RestrictionStatus, landlock::RulesetError> {
+    let abi = ABI::V2;
+    let read_only = AccessFs::from_read(abi);
+    let mut read_paths = vec![config_file.to_path_buf(), fragments_dir(config_file)];
+    read_paths.extend(config.secrets_file.clone());
+    read_paths.extend(config.secret_files.iter().cloned());
+    read_paths.extend(config.http.ca_certificates.iter().cloned());
+    read_paths.extend(std::env::var_os(CREDENTIALS_DIRECTORY_ENV).map(Into::into));
+    read_paths.extend(SYSTEM_PATHS.iter().map(Into::into));
+
+    Ruleset::default()
+        .set_compatibility(compat_level)
+        .handle_access(AccessFs::from_all(abi))?
+        .create()?
+        .add_rules(path_beneath_rules(&read_paths, read_only))?
+        .add_rules(path_beneath_rules(
+            [&config.data_root],
+            AccessFs::from_all(abi),
+        ))?
+        .restrict_self()
+}
+
+fn restrict_syscalls() -> Result<(), CarbondError> {
+    let arch = TargetArch::try_from(std::env::consts::ARCH)
+        .map_err(|e| CarbondError::Sandbox(format!("seccomp: {e}")))?;
+    let filter = syscall_filter(arch)?;
+    seccompiler::apply_filter_all_threads(&filter)
+        .map_err(|e| CarbondError::Sandbox(format!("seccomp: {e}")))
+}
+
+/// Builds a filter allowing only the system calls in [`ALLOWED_SYSCALLS`],
+/// others fail with `EPERM` instead of killing the process.
+fn syscall_filter(arch: TargetArch) -> Result<BpfProgram, CarbondError> {
+    let rules: BTreeMap<i64, _> = ALLOWED_SYSCALLS
+        .iter()
+        .map(|syscall| (*syscall, vec![]))
+        .collect();
+    SeccompFilter::new(
+        rules,
+        SeccompAction::Errno(libc::EPERM as u32),
+        SeccompAction::Allow,
+        arch,
+    )
+    .and_then(BpfProgram::try_from)
+    .map_err(|e| CarbondError::Sandbox(format!("seccomp: {e}")))
+}
+
+/// System calls used by the tokio runtime, reqwest with openssl, name resolution and file I/O.
+const ALLOWED_SYSCALLS: &[i64] = &[
+    // memory
+    libc::SYS_brk,
+    libc::SYS_mmap,
+    libc::SYS_munmap,
+    libc::SYS_mremap,
+    libc::SYS_mprotect,
+    libc::SYS_madvise,
+    libc::SYS_membarrier,
+    // threads and synchronization
+    libc::SYS_clone,
+    libc::SYS_clone3,
+    libc::SYS_futex,
+    libc::SYS_set_robust_list,
+    libc::SYS_rseq,
+    libc::SYS_sched_yield,
+    libc::SYS_sched_getaffinity,
+    libc::SYS_getpid,
+    libc::SYS_gettid,
+    libc::SYS_tgkill,
+    libc::SYS_prctl,
+    libc::SYS_prlimit64,
+    libc::SYS_sysinfo,
+    libc::SYS_exit,
+    libc::SYS_exit_group,
+    // signals
+    libc::SYS_rt_sigaction,
+    libc::SYS_rt_sigprocmask,
+    libc::SYS_rt_sigreturn,
+    libc::SYS_sigaltstack,
+    // time
+    libc::SYS_clock_gettime,
+    libc::SYS_clock_getres,
+    libc::SYS_clock_nanosleep,
+    libc::SYS_gettimeofday,
+    libc::SYS_nanosleep,
+    // event loop
+    libc::SYS_epoll_create1,
+    libc::SYS_epoll_ctl,
+    libc::SYS_epoll_pwait,
+    libc::SYS_eventfd2,
+    libc::SYS_ppoll,
+    libc::SYS_pipe2,
+    // descriptors
+    libc::SYS_read,
+    libc::SYS_readv,
+    libc::SYS_pread64,
+    libc::SYS_write,
+    libc::SYS_writev,
+    libc::SYS_pwrite64,
+    libc::SYS_close,
+    libc::SYS_dup,
+    libc::SYS_dup3,
+    libc::SYS_fcntl,
+    libc::SYS_ioctl,
+    libc::SYS_lseek,
+    libc::SYS_getrandom,
+    // network
+    libc::SYS_socket,
+    libc::SYS_socketpair,
+    libc::SYS_connect,
+    libc::SYS_bind,
+    libc::SYS_getsockname,
+    libc::SYS_getpeername,
+    libc::SYS_getsockopt,
+    libc::SYS_setsockopt,
+    libc::SYS_sendto,
+    libc::SYS_sendmsg,
+    libc::SYS_sendmmsg,
+    libc::SYS_recvfrom,
+    libc::SYS_recvmsg,
+    libc::SYS_shutdown,
+    libc::SYS_uname,
+    // files
+    libc::SYS_openat,
+    libc::SYS_newfstatat,
+    libc::SYS_fstat,
+    libc::SYS_statx,
+    libc::SYS_statfs,
+    libc::SYS_fstatfs,
+    libc::SYS_faccessat,
+    libc::SYS_faccessat2,
+    libc::SYS_readlinkat,
+    libc::SYS_getdents64,
+    libc::SYS_getcwd,
+    libc::SYS_mkdirat,
+    libc::SYS_unlinkat,
+    libc::SYS_renameat,
+    libc::SYS_renameat2,
+    libc::SYS_fchmod,
+    libc::SYS_fchmodat,
+    libc::SYS_ftruncate,
+    libc::SYS_fsync,
+    libc::SYS_fdatasync,
+    // identity
+    libc::SYS_getuid,
+    libc::SYS_geteuid,
+    libc::SYS_getgid,
+    libc::SYS_getegid,
+    // legacy variants only present on x86_64
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_arch_prctl,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_open,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_stat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_lstat,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_access,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_readlink,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_poll,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_epoll_wait,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_chmod,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_mkdir,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_unlink,
+    #[cfg(target_arch = "x86_64")]
+    libc::SYS_rename,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_syscall_filter_compiles() {
+        let arch = TargetArch::try_from(std::env::consts::ARCH).unwrap();
+
+        let filter = syscall_filter(arch).unwrap();
+
+        assert!(filter.len() > ALLOWED_SYSCALLS.len());
+    }
+
+    #[test]
+    fn test_allowed_syscalls_unique() {
+        let mut syscalls = ALLOWED_SYSCALLS.to_vec();
+        syscalls.sort_unstable();
+        syscalls.dedup();
+        assert_eq!(syscalls.len(), ALLOWED_SYSCALLS.len());
+    }
+
+    #[test]
+    fn test_restrict_filesystem() {
+        let (data_root, etc, outside) = (
+            TempDir::new().unwrap(),
+            TempDir::new().unwrap(),
+            TempDir::new().unwrap(),
+        );
+        let config_file = etc.path().join("config.toml");
+        let token_file = etc.path().join("token");
+        std::fs::write(&token_file, "123").unwrap();
+        let raw_config = format!(
+            "update_interval = \"1h\"\ndata_root = \"{}\"\n[intensity_service.electricity_map]\nregion = \"FR\"\ntoken = {{ file = \"{}\" }}\n",
+            data_root.path().display(),
+            token_file.display()
+        );
+        std::fs::write(&config_file, &raw_config).unwrap();
+        std::fs::write(etc.path().join("other"), "").unwrap();
+        let config = Config::try_parse(&raw_config).unwrap();
+
+        // Landlock only restricts the calling thread and its children
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let status =
+                    restrict_filesystem(&config, &config_file, CompatLevel::BestEffort).unwrap();
+
+                std::fs::write(data_root.path().join("metric"), "1 g/kWh").unwrap();
+                assert!(std::fs::read_to_string(&config_file).is_ok());
+                assert!(std::fs::read_to_string(&token_file).is_ok());
+                assert!(std::fs::metadata("/proc/self").is_ok());
+                if status.ruleset != RulesetStatus::NotEnforced {
+                    assert!(std::fs::write(outside.path().join("metric"), "1 g/kWh").is_err());
+                    assert!(std::fs::read_to_string(etc.path().join("other")).is_err());
+                }
+            });
+        });
+    }
+
+    #[test]
+    fn test_restrict_filesystem_covers_runtime_threads() {
+        let (data_root, etc, outside) = (
+            TempDir::new().unwrap(),
+            TempDir::new().unwrap(),
+            TempDir::new().unwrap(),
+        );
+        let config_file = etc.path().join("config.toml");
+        let raw_config = format!(
+            "update_interval = \"1h\"\ndata_root = \"{}\"\n[intensity_service.electricity_map]\nregion = \"FR\"\ntoken = \"123\"\n",
+            data_root.path().display()
+        );
+        std::fs::write(&config_file, &raw_config).unwrap();
+        let forbidden = outside.path().join("secret");
+        std::fs::write(&forbidden, "123").unwrap();
+        let config = Config::try_parse(&raw_config).unwrap();
+
+        std::thread::scope(|scope| {
+            scope.spawn(|| {
+                let status =
+                    restrict_filesystem(&config, &config_file, CompatLevel::BestEffort).unwrap();
+                if status.ruleset == RulesetStatus::NotEnforced {
+                    return;
+                }
+
+                // workers and blocking threads are started after the restriction, as in main
+                let runtime = tokio::runtime::Builder::new_multi_thread()
+                    .enable_all()
+                    .build()
+                    .unwrap();
+                runtime.block_on(async {
+                    let path = forbidden.clone();
+                    let blocking = tokio::task::spawn_blocking(move || std::fs::read(path));
+                    assert!(blocking.await.unwrap().is_err());
+                    let worker = tokio::spawn(tokio::fs::read(forbidden.clone()));
+                    assert!(worker.await.unwrap().is_err());
+                });
+            });
+        });
+    }
+}
@@ -1,32 +1,82 @@
-use std::{future::Future, sync::Arc, time::Duration};
+use std::{collections::HashMap, future::Future, sync::Arc, time::Duration};
 
 use async_channel::{unbounded, Receiver, Sender};
-use log::{debug, error, info};
+use chrono::{DateTime, Utc};
+use log::{debug, error, info, warn};
 use tokio::{
     signal::unix::{signal, SignalKind},
-    sync::Mutex,
+    sync::{watch, Mutex},
     task::{self, JoinHandle},
-    time,
+    time::{self, Instant, Interval, MissedTickBehavior},
 };
 
 use crate::{
-    data::{config::internal::Config, state::State},
+    data::{
+        config::internal::{Config, ErrorPolicy, JobPolicy, Schedule},
+        state::State,
+    },
     errors::CarbondError,
+    systemd, update_cpu_intensity, ConfigSource,
 };
 
+/// Time a single run of a job may take before the job counts as stuck.
+/// The watchdog is not pinged while a job is stuck, so systemd restarts carbond.
+const STUCK_AFTER: Duration = Duration::from_secs(15 * 60);
+
 /// Scheduler for the execution of carbond jobs in the background at given intervals.
 /// Can be awaited to sleep until an error occurs or an interrupt signal is received.
+/// Reloads the config on a hangup signal if it knows the config's source.
+/// In oneshot mode, every job that is not paused runs a single time and awaiting ends
+/// once all jobs are done.
 pub struct Scheduler {
     cfg: Arc<Mutex<Config>>,
     state: Arc<Mutex<State>>,
     handles: Vec<JoinHandle<()>>,
     tx: Sender<CarbondError>,
     rx: Receiver<CarbondError>,
+    reload_tx: watch::Sender<()>,
+    shutdown_tx: watch::Sender<bool>,
+    source: Option<ConfigSource>,
+    oneshot: bool,
+    progress: Arc<Progress>,
+}
+
+/// Start of the job runs in progress, used to detect stuck jobs.
+#[derive(Default)]
+struct Progress {
+    running: std::sync::Mutex<HashMap<String, Instant>>,
+}
+
+impl Progress {
+    #[allow(clippy::unwrap_used)]
+    fn started(&self, job: &str) {
+        self.running
+            .lock()
+            .unwrap()
+            .insert(job.to_owned(), Instant::now());
+    }
+
+    #[allow(clippy::unwrap_used)]
+    fn finished(&self, job: &str) {
+        self.running.lock().unwrap().remove(job);
+    }
+
+    /// Whether every job run in progress started within the limit.
+    #[allow(clippy::unwrap_used)]
+    fn is_alive(&self, limit: Duration) -> bool {
+        self.running
+            .lock()
+            .unwrap()
+            .values()
+            .all(|started| started.elapsed() <= limit)
+    }
 }
 
 impl Scheduler {
     pub fn new(cfg: Config, state: State) -> Self {
         let (tx, rx) = unbounded::<CarbondError>();
+        let (reload_tx, _) = watch::channel(());
+        let (shutdown_tx, _) = watch::channel(false);
 
         Scheduler {
             cfg: Arc::new(Mutex::new(cfg)),
@@ -34,26 +84,65 @@ impl Scheduler {
             handles: vec![],
             tx,
             rx,
+            reload_tx,
+            shutdown_tx,
+            source: None,
+            oneshot: false,
+            progress: Arc::new(Progress::default()),
         }
     }
 
-    /// Calls abort on all of this scheduler's JoinHandles.
-    /// Then, checks if all jobs have been canceled successfully.
-    #[allow(clippy::unwrap_used)]
+    /// Runs every scheduled job a single time instead of at its interval.
+    /// Paused jobs do not run.
+    pub fn oneshot(mut self, oneshot: bool) -> Self {
+        self.oneshot = oneshot;
+        self
+    }
+
+    /// Reloads the config from the given source on a hangup signal.
+    pub fn reload_from(mut self, source: ConfigSource) -> Self {
+        self.source = Some(source);
+        self
+    }
+
+    /// Stops all jobs and flushes the state to the file system.
+    /// Running jobs may finish within the configured shutdown timeout,
+    /// jobs still running afterwards are aborted.
     pub async fn shutdown(&mut self) {
-        for handle in &self.handles {
-            handle.abort();
+        systemd::notify("STOPPING=1");
+        self.shutdown_tx.send_replace(true);
+        let timeout = self.cfg.lock().await.shutdown_timeout;
+        let mut handles: Vec<_> = self.handles.drain(..).collect();
+        let finished = time::timeout(timeout, async {
+            for handle in &mut handles {
+                log_join_error(handle.await);
+            }
+        })
+        .await;
+        if finished.is_err() {
+            warn!("Jobs did not finish within {timeout:?}, aborting them.");
+            for handle in &handles {
+                handle.abort();
+            }
+            for handle in handles {
+                log_join_error(handle.await);
+            }
         }
-
-        for handle in &mut self.handles {
-            assert!(handle.await.unwrap_err().is_cancelled());
+        let data_root = self.cfg.lock().await.data_root.clone();
+        if let Err(e) = self.state.lock().await.flush(&data_root).await {
+            error!("Could not flush state: {:?}", e);
         }
     }
 
-    /// Schedules a carbond job to run in the background at a specified interval.
-    /// Adds error handling to the Future and sends errors through a channel.
+    /// Schedules a carbond job to run in the background.
+    /// The job's policy is taken from the current config, so its schedule follows
+    /// config reloads. Jobs run right away and then according to their schedule,
+    /// jobs without a schedule are paused until a config enables them.
+    /// Failed runs are handled according to the job's policy, jobs reporting stale values
+    /// end the service like jobs exceeding their maximum staleness.
+    /// Errors that end the service are sent through a channel.
     #[allow(clippy::unwrap_used)]
-    pub fn schedule_job<F, Fut>(&mut self, name: String, duration: Duration, f: F)
+    pub fn schedule_job<F, Fut>(&mut self, name: String, policy: fn(&Config) -> JobPolicy, f: F)
     where
         F: FnOnce(Arc<Mutex<Config>>, Arc<Mutex<State>>) -> Fut + Send + Copy + 'static,
         Fut: Future<Output = Result<(), CarbondError>> + Send,
@@ -61,36 +150,530 @@ impl Scheduler {
         let config = self.cfg.clone();
         let state = self.state.clone();
         let job_tx = self.tx.clone();
+        let mut reload_rx = self.reload_tx.subscribe();
+        let mut shutdown_rx = self.shutdown_tx.subscribe();
+        let progress = self.progress.clone();
+        if self.oneshot {
+            debug!("Running task once: {name}");
+            let job = task::spawn(async move {
+                let policy = policy(&*config.lock().await);
+                if policy.schedule.is_none() {
+                    debug!("Task {name} is paused, not running it.");
+                    return;
+                }
+                let result = run_job(
+                    &name,
+                    &policy.on_error,
+                    f,
+                    &config,
+                    &state,
+                    &progress,
+                    &mut shutdown_rx,
+                )
+                .await;
+                if let Err(e) = result {
+                    error!("Task {name} failed: {:?}", e);
+                    job_tx.send(e).await.unwrap();
+                }
+            });
+            self.handles.push(job);
+            return;
+        }
         debug!("Scheduling task: {name}");
         let job = task::spawn(async move {
-            let mut interval = time::interval(duration);
+            let mut current = policy(&*config.lock().await).schedule;
+            let mut next = current.as_ref().map(|_| Instant::now());
+            let mut last_success = Instant::now();
             loop {
-                interval.tick().await;
+                tokio::select! {
+                    _ = sleep_until(next) => {},
+                    _ = stopped(&mut shutdown_rx) => break,
+                    _ = reloaded(&mut reload_rx) => {
+                        let new = policy(&*config.lock().await).schedule;
+                        if new != current {
+                            info!("Rescheduling task {name} to {:?}.", new);
+                            next = new.as_ref().and_then(|schedule| next_run(schedule, Instant::now()));
+                            current = new;
+                            last_success = Instant::now();
+                        }
+                        continue;
+                    }
+                }
+                next = current
+                    .as_ref()
+                    .zip(next)
+                    .and_then(|(schedule, previous)| next_run(schedule, previous));
                 debug!("Running task: {name}");
-                let config = config.clone();
-                let state = state.clone();
-                if let Err(e) = f(config, state).await {
-                    job_tx.send(e).await.unwrap();
+                let policy = policy(&*config.lock().await);
+                let result = run_job(
+                    &name,
+                    &policy.on_error,
+                    f,
+                    &config,
+                    &state,
+                    &progress,
+                    &mut shutdown_rx,
+                )
+                .await;
+                let e = match result {
+                    Ok(()) => {
+                        last_success = Instant::now();
+                        continue;
+                    }
+                    Err(e) => e,
                 };
+                let is_stale = matches!(e, CarbondError::Stale { .. });
+                if is_stale || policy.on_error == ErrorPolicy::Fatal && !e.is_transient() {
+                    job_tx.send(e).await.unwrap();
+                    break;
+                }
+                warn!("Task {name} failed, keeping last value: {:?}", e);
+                let stale_for = last_success.elapsed();
+                if policy.max_staleness.is_some_and(|max| stale_for > max) {
+                    job_tx
+                        .send(CarbondError::Stale {
+                            job: name.clone(),
+                            stale_for,
+                        })
+                        .await
+                        .unwrap();
+                    break;
+                }
             }
         });
         self.handles.push(job);
     }
 
-    /// Waits until service is ended by either an interrupt signal or any carbond service runs into an error.
+    /// Waits until service is ended by either an interrupt or terminate signal or any carbond job runs into a fatal error.
+    /// Reloads the config on hangup signals and pings the systemd watchdog in the meantime
+    /// unless a job is stuck.
+    /// In oneshot mode, waits until all jobs are done and returns the first error,
+    /// or the first panic if no job returned an error.
     #[allow(clippy::unwrap_used)]
-    pub async fn await_service_end(&self) -> Result<(), CarbondError> {
-        let mut res = Ok(());
-        let mut sig = signal(SignalKind::interrupt()).unwrap();
-        tokio::select! {
-            _ = sig.recv() => {
-                info!("Interrupt signal received.");
-            },
-            e = self.rx.recv() => {
-                error!("Received through error channel: {:?}", e);
-                res = Err(e.unwrap());
+    pub async fn await_service_end(&mut self) -> Result<(), CarbondError> {
+        if self.oneshot {
+            let mut panic = None;
+            for handle in self.handles.drain(..) {
+                match handle.await {
+                    Err(e) if e.is_panic() => {
+                        error!("Task panicked: {:?}", e);
+                        panic.get_or_insert(e.to_string());
+                    }
+                    _ => {}
+                }
+            }
+            return match self.rx.try_recv() {
+                Ok(e) => Err(e),
+                Err(_) => panic.map_or(Ok(()), |e| Err(CarbondError::Panicked(e))),
+            };
+        }
+        let mut interrupt = signal(SignalKind::interrupt()).unwrap();
+        let mut terminate = signal(SignalKind::terminate()).unwrap();
+        let mut hangup = signal(SignalKind::hangup()).unwrap();
+        let mut watchdog =
+            systemd::watchdog_interval().map(|period| new_ticker(Instant::now(), period));
+        loop {
+            tokio::select! {
+                _ = tick(&mut watchdog) => {
+                    if self.progress.is_alive(STUCK_AFTER) {
+                        systemd::notify("WATCHDOG=1");
+                    } else {
+                        warn!("A job is stuck for more than {STUCK_AFTER:?}, not pinging the watchdog.");
+                    }
+                },
+                _ = interrupt.recv() => {
+                    info!("Interrupt signal received.");
+                    return Ok(());
+                },
+                _ = terminate.recv() => {
+                    info!("Terminate signal received.");
+                    return Ok(());
+                },
+                _ = hangup.recv() => {
+                    info!("Hangup signal received, reloading config.");
+                    systemd::notify("RELOADING=1");
+                    self.reload().await;
+                    systemd::notify("READY=1");
+                },
+                e = self.rx.recv() => {
+                    error!("Received through error channel: {:?}", e);
+                    return Err(e.unwrap());
+                }
+            }
+        }
+    }
+
+    /// Loads the config from its source and swaps it for the running jobs.
+    /// Recomputes device metrics and reschedules jobs whose interval changed.
+    /// An invalid config is ignored and the current config is kept.
+    async fn reload(&self) {
+        let Some(source) = &self.source else {
+            warn!("Config source unknown, not reloading.");
+            return;
+        };
+        let config = match source.load_existing().await {
+            Ok(config) => config,
+            Err(e) => {
+                error!("Ignoring invalid config: {:?}", e);
+                return;
             }
         };
-        res
+        if let Err(e) = update_cpu_intensity(&config).await {
+            warn!("Could not update cpu intensity: {:?}", e);
+        }
+        *self.cfg.lock().await = config;
+        self.reload_tx.send_replace(());
+        info!("Config reloaded.");
+    }
+}
+
+/// Creates a ticker with the given period that ticks first at `start`.
+fn new_ticker(start: Instant, period: Duration) -> Interval {
+    let mut ticker = time::interval_at(start, period);
+    ticker.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    ticker
+}
+
+/// Calculates when a job runs next after its previous run was due.
+/// Runs missed while the job was still busy are skipped.
+fn next_run(schedule: &Schedule, previous: Instant) -> Option<Instant> {
+    let now = Instant::now();
+    match schedule {
+        Schedule::Every {
+            period,
+            aligned: false,
+        } => {
+            let missed = now.saturating_duration_since(previous).as_nanos() / period.as_nanos();
+            let periods = u32::try_from(missed + 1).unwrap_or(u32::MAX);
+            Some(previous + period.saturating_mul(periods))
+        }
+        Schedule::Every {
+            period,
+            aligned: true,
+        } => Some(now + until_aligned(Utc::now(), *period)),
+        Schedule::Cron(cron) => {
+            let wall_now = Utc::now();
+            let next = cron.after(&wall_now).next()?;
+            Some(now + (next - wall_now).to_std().unwrap_or_default())
+        }
+    }
+}
+
+/// Time until the next multiple of the period since the Unix epoch.
+fn until_aligned(now: DateTime<Utc>, period: Duration) -> Duration {
+    let period = period.as_millis().max(1);
+    let since_epoch = u128::try_from(now.timestamp_millis()).unwrap_or_default();
+    Duration::from_millis((period - since_epoch % period) as u64)
+}
+
+/// Waits until a job is due, forever if the job is paused.
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Waits for the next tick, forever if the job is paused.
+async fn tick(ticker: &mut Option<Interval>) {
+    match ticker {
+        Some(ticker) => {
+            ticker.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Waits until the scheduler shuts down.
+async fn stopped(shutdown_rx: &mut watch::Receiver<bool>) {
+    if shutdown_rx.wait_for(|stopped| *stopped).await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+fn log_join_error(result: Result<(), task::JoinError>) {
+    match result {
+        Err(e) if e.is_panic() => error!("Task panicked: {:?}", e),
+        _ => {}
+    }
+}
+
+/// Waits until the config was reloaded.
+async fn reloaded(reload_rx: &mut watch::Receiver<()>) {
+    if reload_rx.changed().await.is_err() {
+        std::future::pending::<()>().await;
+    }
+}
+
+/// Runs a job once and retries transient errors if the policy allows it.
+/// Every attempt is tracked in the progress, waiting for a retry is not.
+/// Stops retrying once the scheduler shuts down.
+async fn run_job<F, Fut>(
+    name: &str,
+    policy: &ErrorPolicy,
+    f: F,
+    config: &Arc<Mutex<Config>>,
+    state: &Arc<Mutex<State>>,
+    progress: &Progress,
+    shutdown_rx: &mut watch::Receiver<bool>,
+) -> Result<(), CarbondError>
+where
+    F: FnOnce(Arc<Mutex<Config>>, Arc<Mutex<State>>) -> Fut + Copy,
+    Fut: Future<Output = Result<(), CarbondError>>,
+{
+    let mut attempt = 0;
+    loop {
+        progress.started(name);
+        let result = f(config.clone(), state.clone()).await;
+        progress.finished(name);
+        let e = match result {
+            Ok(()) => return Ok(()),
+            Err(e) => e,
+        };
+        match policy {
+            ErrorPolicy::Retry {
+                max_retries,
+                base_delay,
+                max_delay,
+            } if e.is_transient() && attempt < *max_retries => {
+                let delay = backoff_delay(attempt, *base_delay, *max_delay);
+                warn!("Task {name} failed, retrying in {delay:?}: {:?}", e);
+                tokio::select! {
+                    _ = time::sleep(delay) => {},
+                    _ = stopped(shutdown_rx) => return Err(e),
+                }
+                attempt += 1;
+            }
+            _ => return Err(e),
+        }
+    }
+}
+
+/// Calculates the delay before a retry.
+/// The delay doubles with every attempt up to `max_delay` and is jittered
+/// randomly between half and the full delay.
+fn backoff_delay(attempt: u32, base_delay: Duration, max_delay: Duration) -> Duration {
+    let delay = base_delay
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(max_delay);
+    delay / 2 + delay.mul_f64(fastrand::f64() / 2.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use tempfile::TempDir;
+
+    async fn scheduler_with_source(raw_config: &str) -> (Scheduler, TempDir) {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("config.toml");
+        let config = Config::try_parse("update_interval = \"1h\"\n[intensity_service]\n").unwrap();
+        let state = State::new(dir.path()).await;
+        tokio::fs::write(&path, raw_config).await.unwrap();
+        let scheduler = Scheduler::new(config, state).reload_from(ConfigSource {
+            path: path.clone(),
+            ..Default::default()
+        });
+        (scheduler, dir)
+    }
+
+    #[tokio::test]
+    async fn test_reload_swaps_config() {
+        let (scheduler, _dir) =
+            scheduler_with_source("update_interval = \"5m\"\n[intensity_service]\n").await;
+        let reload_rx = scheduler.reload_tx.subscribe();
+
+        scheduler.reload().await;
+
+        assert_eq!(
+            scheduler.cfg.lock().await.update_interval,
+            Duration::from_secs(5 * 60)
+        );
+        assert!(reload_rx.has_changed().unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_aborts_jobs_after_timeout() {
+        let (mut scheduler, _dir) = scheduler_with_source("").await;
+        scheduler.cfg.lock().await.shutdown_timeout = Duration::from_millis(100);
+        scheduler.schedule_job(
+            String::from("Sleep"),
+            |cfg| cfg.jobs.carbon_intensity.clone(),
+            |_, _| async {
+                time::sleep(Duration::from_secs(60)).await;
+                Ok(())
+            },
+        );
+        time::sleep(Duration::from_millis(10)).await;
+
+        let started = Instant::now();
+        scheduler.shutdown().await;
+
+        assert!(started.elapsed() < Duration::from_secs(10));
+    }
+
+    #[tokio::test]
+    async fn test_shutdown_after_job_ended() {
+        let (mut scheduler, _dir) = scheduler_with_source("").await;
+        scheduler.schedule_job(
+            String::from("Fail"),
+            |_| JobPolicy {
+                schedule: Some(Schedule::Every {
+                    period: Duration::from_secs(60),
+                    aligned: false,
+                }),
+                on_error: ErrorPolicy::Fatal,
+                max_staleness: None,
+            },
+            |_, _| async {
+                Err(CarbondError::Stale {
+                    job: String::from("Fail"),
+                    stale_for: Duration::ZERO,
+                })
+            },
+        );
+        assert!(scheduler.rx.recv().await.is_ok());
+
+        scheduler.shutdown().await;
+
+        assert!(scheduler.handles.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_oneshot_skips_paused_jobs() {
+        static RAN: AtomicBool = AtomicBool::new(false);
+        let (scheduler, _dir) = scheduler_with_source("").await;
+        let mut scheduler = scheduler.oneshot(true);
+        scheduler.schedule_job(
+            String::from("Paused"),
+            |_| JobPolicy {
+                schedule: None,
+                on_error: ErrorPolicy::Fatal,
+                max_staleness: None,
+            },
+            |_, _| async {
+                RAN.store(true, Ordering::SeqCst);
+                Ok(())
+            },
+        );
+
+        assert!(scheduler.await_service_end().await.is_ok());
+        assert!(!RAN.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_oneshot_returns_panics() {
+        let (scheduler, _dir) = scheduler_with_source("").await;
+        let mut scheduler = scheduler.oneshot(true);
+        scheduler.schedule_job(
+            String::from("Panicking"),
+            |_| JobPolicy {
+                schedule: Some(Schedule::Every {
+                    period: Duration::from_secs(60),
+                    aligned: false,
+                }),
+                on_error: ErrorPolicy::Fatal,
+                max_staleness: None,
+            },
+            |_, _| async { panic!("job failed") },
+        );
+
+        assert!(matches!(
+            scheduler.await_service_end().await,
+            Err(CarbondError::Panicked(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_reload_ignores_invalid_config() {
+        let (scheduler, _dir) = scheduler_with_source("update_interval = \"5 minutes\"\n").await;
+        let reload_rx = scheduler.reload_tx.subscribe();
+
+        scheduler.reload().await;
+
+        assert_eq!(
+            scheduler.cfg.lock().await.update_interval,
+            Duration::from_secs(60 * 60)
+        );
+        assert!(!reload_rx.has_changed().unwrap());
+    }
+
+    #[test]
+    fn test_progress_detects_stuck_jobs() {
+        let progress = Progress::default();
+        assert!(progress.is_alive(Duration::ZERO));
+
+        progress.started("Job");
+        std::thread::sleep(Duration::from_millis(1));
+        assert!(!progress.is_alive(Duration::ZERO));
+        assert!(progress.is_alive(Duration::from_secs(60)));
+
+        progress.finished("Job");
+        assert!(progress.is_alive(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_until_aligned() {
+        let now = DateTime::parse_from_rfc3339("2023-05-01T12:03:20Z")
+            .unwrap()
+            .with_timezone(&Utc);
+
+        assert_eq!(
+            until_aligned(now, Duration::from_secs(5 * 60)),
+            Duration::from_secs(100)
+        );
+        assert_eq!(
+            until_aligned(now, Duration::from_secs(60 * 60)),
+            Duration::from_secs(56 * 60 + 40)
+        );
+    }
+
+    #[test]
+    fn test_next_run_skips_missed_runs() {
+        let schedule = Schedule::Every {
+            period: Duration::from_secs(60),
+            aligned: false,
+        };
+        let now = Instant::now();
+
+        assert_eq!(
+            next_run(&schedule, now),
+            Some(now + Duration::from_secs(60))
+        );
+        let previous = now - Duration::from_secs(150);
+        assert_eq!(
+            next_run(&schedule, previous),
+            Some(previous + Duration::from_secs(180))
+        );
+    }
+
+    #[test]
+    fn test_next_run_cron() {
+        let schedule = Schedule::Cron(Box::new("0 */5 * * * *".parse().unwrap()));
+
+        let next = next_run(&schedule, Instant::now()).unwrap();
+
+        assert!(next <= Instant::now() + Duration::from_secs(5 * 60));
+    }
+
+    #[test]
+    fn test_backoff_delay_grows_exponentially() {
+        let base = Duration::from_secs(10);
+        let max = Duration::from_secs(300);
+
+        for (attempt, expected) in [(0, 10), (1, 20), (2, 40), (3, 80)] {
+            let delay = backoff_delay(attempt, base, max);
+            assert!(delay >= Duration::from_secs(expected) / 2);
+            assert!(delay <= Duration::from_secs(expected));
+        }
+    }
+
+    #[test]
+    fn test_backoff_delay_is_capped() {
+        let delay = backoff_delay(20, Duration::from_secs(10), Duration::from_secs(300));
+
+        assert!(delay >= Duration::from_secs(150));
+        assert!(delay <= Duration::from_secs(300));
     }
 }
@@ -0,0 +1,211 @@
+use std::{
+    ffi::OsStr,
+    io,
+    os::unix::{ffi::OsStrExt, fs::MetadataExt, net::UnixDatagram},
+    time::Duration,
+};
+
+use log::{Level, Log, Metadata, Record};
+
+const JOURNAL_SOCKET: &str = "/run/systemd/journal/socket";
+const IDENTIFIER: &str = "carbond";
+
+/// Sends a state like `READY=1` to the service manager.
+/// Does nothing if carbond is not started by systemd with `Type=notify`.
+pub fn notify(state: &str) {
+    if let Some(socket) = std::env::var_os("NOTIFY_SOCKET") {
+        if let Err(e) = notify_socket(&socket, state) {
+            log::warn!("Could not notify service manager: {:?}", e);
+        }
+    }
+}
+
+/// Interval in which the watchdog has to be pinged with `WATCHDOG=1`.
+/// Pings are due twice as often as systemd expects them to tolerate delays.
+pub fn watchdog_interval() -> Option<Duration> {
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    let pid_matches = std::env::var("WATCHDOG_PID")
+        .map(|pid| pid == std::process::id().to_string())
+        .unwrap_or(true);
+    (pid_matches && usec > 0).then(|| Duration::from_micros(usec) / 2)
+}
+
+/// Checks if stderr is connected to the journal as announced by `JOURNAL_STREAM`.
+pub fn is_journal_stream() -> bool {
+    let Some(stream) = std::env::var_os("JOURNAL_STREAM") else {
+        return false;
+    };
+    match std::fs::metadata("/proc/self/fd/2") {
+        Ok(stderr) => stream.as_bytes() == format!("{}:{}", stderr.dev(), stderr.ino()).as_bytes(),
+        Err(_) => true,
+    }
+}
+
+fn notify_socket(socket: &OsStr, state: &str) -> io::Result<()> {
+    let datagram = UnixDatagram::unbound()?;
+    match socket.as_bytes().strip_prefix(b"@") {
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let address = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            datagram.send_to_addr(state.as_bytes(), &address)?;
+        }
+        None => {
+            datagram.send_to(state.as_bytes(), socket)?;
+        }
+    }
+    Ok(())
+}
+
+/// Logger sending structured entries to journald via its native protocol.
+pub struct JournalLogger {
+    socket: UnixDatagram,
+    path: String,
+    level: log::LevelFilter,
+}
+
+impl JournalLogger {
+    /// Connects to the journal with the verbosity levels used by `stderrlog`.
+    pub fn new(verbosity: usize) -> io::Result<Self> {
+        Self::with_socket(JOURNAL_SOCKET, verbosity)
+    }
+
+    fn with_socket(path: &str, verbosity: usize) -> io::Result<Self> {
+        let level = match verbosity {
+            0 => log::LevelFilter::Error,
+            1 => log::LevelFilter::Warn,
+            2 => log::LevelFilter::Info,
+            3 => log::LevelFilter::Debug,
+            _ => log::LevelFilter::Trace,
+        };
+        Ok(JournalLogger {
+            socket: UnixDatagram::unbound()?,
+            path: path.to_owned(),
+            level,
+        })
+    }
+
+    /// Installs the logger as global logger.
+    pub fn init(self) -> Result<(), log::SetLoggerError> {
+        log::set_max_level(self.level);
+        log::set_boxed_logger(Box::new(self))
+    }
+}
+
+impl Log for JournalLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let _ = self.socket.send_to(&journal_entry(record), &self.path);
+    }
+
+    fn flush(&self) {}
+}
+
+/// Serializes a log record into fields of the journal export format.
+fn journal_entry(record: &Record) -> Vec<u8> {
+    let priority = match record.level() {
+        Level::Error => "3",
+        Level::Warn => "4",
+        Level::Info => "6",
+        Level::Debug | Level::Trace => "7",
+    };
+    let mut entry = vec![];
+    append_field(&mut entry, "MESSAGE", &record.args().to_string());
+    append_field(&mut entry, "PRIORITY", priority);
+    append_field(&mut entry, "SYSLOG_IDENTIFIER", IDENTIFIER);
+    append_field(&mut entry, "TARGET", record.target());
+    if let Some(module) = record.module_path() {
+        append_field(&mut entry, "CODE_MODULE", module);
+    }
+    if let Some(file) = record.file() {
+        append_field(&mut entry, "CODE_FILE", file);
+    }
+    if let Some(line) = record.line() {
+        append_field(&mut entry, "CODE_LINE", &line.to_string());
+    }
+    entry
+}
+
+/// Appends a field, values with line breaks are prefixed by their length.
+fn append_field(entry: &mut Vec<u8>, key: &str, value: &str) {
+    entry.extend_from_slice(key.as_bytes());
+    if value.contains('\n') {
+        entry.push(b'\n');
+        entry.extend_from_slice(&(value.len() as u64).to_le_bytes());
+    } else {
+        entry.push(b'=');
+    }
+    entry.extend_from_slice(value.as_bytes());
+    entry.push(b'\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_notify_socket() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("notify.sock");
+        let server = UnixDatagram::bind(&path).unwrap();
+
+        notify_socket(path.as_os_str(), "READY=1\nSTATUS=Running").unwrap();
+
+        let mut buffer = [0; 64];
+        let len = server.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], b"READY=1\nSTATUS=Running");
+    }
+
+    #[test]
+    fn test_notify_abstract_socket() {
+        use std::os::linux::net::SocketAddrExt;
+        let name = format!("carbond-notify-{}", fastrand::u64(..));
+        let address = std::os::unix::net::SocketAddr::from_abstract_name(&name).unwrap();
+        let server = UnixDatagram::bind_addr(&address).unwrap();
+
+        notify_socket(OsStr::new(&format!("@{name}")), "WATCHDOG=1").unwrap();
+
+        let mut buffer = [0; 64];
+        let len = server.recv(&mut buffer).unwrap();
+        assert_eq!(&buffer[..len], b"WATCHDOG=1");
+    }
+
+    #[test]
+    fn test_journal_logger() {
+        let dir = TempDir::new().unwrap();
+        let path = dir.path().join("journal.sock");
+        let server = UnixDatagram::bind(&path).unwrap();
+        let logger = JournalLogger::with_socket(path.to_str().unwrap(), 2).unwrap();
+
+        logger.log(
+            &Record::builder()
+                .args(format_args!("first\nsecond"))
+                .level(Level::Warn)
+                .target("carbond::scheduler")
+                .build(),
+        );
+
+        let mut buffer = [0; 256];
+        let len = server.recv(&mut buffer).unwrap();
+        let mut expected = b"MESSAGE\n".to_vec();
+        expected.extend_from_slice(&12u64.to_le_bytes());
+        expected.extend_from_slice(
+            b"first\nsecond\nPRIORITY=4\nSYSLOG_IDENTIFIER=carbond\nTARGET=carbond::scheduler\n",
+        );
+        assert_eq!(&buffer[..len], &expected[..]);
+    }
+
+    #[test]
+    fn test_journal_logger_filters_level() {
+        let logger = JournalLogger::with_socket("/nonexistent", 1).unwrap();
+
+        assert!(logger.enabled(&Metadata::builder().level(Level::Warn).build()));
+        assert!(!logger.enabled(&Metadata::builder().level(Level::Info).build()));
+    }
+}